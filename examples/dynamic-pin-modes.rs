@@ -0,0 +1,78 @@
+//! Checks on the target that every mode of a `DynamicPin` configures the port registers like
+//! the matching fully typed pin.
+//!
+//! PA5 is switched through all `Dynamic` modes while PA6 is converted to the same modes with
+//! the `into_*` methods, and MODER, PUPDR and OTYPER of both pins are compared after each step.
+//! The result is printed over semihosting, a mismatch panics.
+
+#![no_main]
+#![no_std]
+
+use panic_semihosting as _;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use stm32f4xx_hal as hal;
+
+use crate::hal::{
+    gpio::{Dynamic, PinState},
+    pac,
+    prelude::*,
+};
+
+/// MODER, PUPDR and OTYPER fields of pin `n` of GPIOA
+fn config(n: u32) -> (u32, u32, u32) {
+    // NOTE(unsafe) read only access to the configuration registers
+    let gpioa = unsafe { &*pac::GPIOA::ptr() };
+    (
+        (gpioa.moder.read().bits() >> (2 * n)) & 0b11,
+        (gpioa.pupdr.read().bits() >> (2 * n)) & 0b11,
+        (gpioa.otyper.read().bits() >> n) & 0b1,
+    )
+}
+
+fn check(mode: Dynamic, dynamic: Dynamic) {
+    assert_eq!(dynamic, mode);
+    assert_eq!(config(5), config(6), "{:?}", mode);
+    hprintln!("{:?} ok", mode).ok();
+}
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+    let gpioa = dp.GPIOA.split();
+
+    let mut dynamic = gpioa.pa5.into_dynamic();
+    let typed = gpioa.pa6.into_floating_input();
+    check(Dynamic::InputFloating, dynamic.mode());
+
+    dynamic.make_pull_up_input();
+    let typed = typed.into_pull_up_input();
+    check(Dynamic::InputPullUp, dynamic.mode());
+
+    dynamic.make_pull_down_input();
+    let typed = typed.into_pull_down_input();
+    check(Dynamic::InputPullDown, dynamic.mode());
+
+    dynamic.make_push_pull_output();
+    let typed = typed.into_push_pull_output();
+    check(Dynamic::OutputPushPull, dynamic.mode());
+
+    dynamic.make_open_drain_output();
+    let typed = typed.into_open_drain_output();
+    check(Dynamic::OutputOpenDrain, dynamic.mode());
+
+    dynamic.make_push_pull_output_in_state(PinState::High);
+    let typed = typed.into_push_pull_output_in_state(PinState::High);
+    check(Dynamic::OutputPushPull, dynamic.mode());
+
+    dynamic.make_floating_input();
+    let _typed = typed.into_floating_input();
+    check(Dynamic::InputFloating, dynamic.mode());
+
+    hprintln!("all modes match").ok();
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
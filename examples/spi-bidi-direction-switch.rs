@@ -0,0 +1,86 @@
+//! Checks on the target that switching the data line direction in bidirectional mode doesn't
+//! emit stray clock edges.
+//!
+//! SPI1 runs in bidirectional mode on PA5 (SCK) and PA7 (MOSI/MISO) with a slow clock. EXTI
+//! counts the rising SCK edges on PA5, which still works with the pin in alternate mode. Two
+//! frames are written and two read with `read_into`, which switches BIDIOE to input and back
+//! with SPE cleared around each change. An extra edge from a switch with the bus enabled would
+//! leave the count off a whole number of 8 bit frames. Afterwards BIDIOE and SPE have to be set
+//! again. The result is printed over semihosting, a mismatch panics.
+
+#![no_main]
+#![no_std]
+
+use panic_semihosting as _;
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use stm32f4xx_hal::{
+    gpio::{Edge, ExtiPin},
+    pac::{self, interrupt, Interrupt},
+    prelude::*,
+    spi::*,
+};
+
+static SCK_EDGES: AtomicU32 = AtomicU32::new(0);
+
+#[interrupt]
+fn EXTI9_5() {
+    // NOTE(unsafe) write 1 to clear only the pending bit of line 5
+    unsafe { (*pac::EXTI::ptr()).pr.write(|w| w.bits(1 << 5)) };
+    SCK_EDGES.fetch_add(1, Ordering::Relaxed);
+}
+
+#[entry]
+fn main() -> ! {
+    let mut dp = pac::Peripherals::take().unwrap();
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.freeze();
+    let mut syscfg = dp.SYSCFG.constrain();
+
+    let gpioa = dp.GPIOA.split();
+
+    // EXTI is set up while the pin is an input, the alternate mode keeps the line connected
+    let mut sck = gpioa.pa5.into_floating_input();
+    sck.make_interrupt_source(&mut syscfg);
+    sck.trigger_on_edge(&mut dp.EXTI, Edge::Rising);
+    sck.enable_interrupt(&mut dp.EXTI);
+    let sck = sck.into_alternate();
+    let mosi = gpioa.pa7.into_alternate();
+
+    let mode = Mode {
+        polarity: Polarity::IdleLow,
+        phase: Phase::CaptureOnFirstTransition,
+    };
+    let mut spi = Spi::new_bidi(dp.SPI1, (sck, NoMiso {}, mosi), mode, 100_000.hz(), &clocks);
+
+    SCK_EDGES.store(0, Ordering::Relaxed);
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(Interrupt::EXTI9_5);
+    }
+
+    spi.write(&[0xa5u8, 0x5a]).unwrap();
+    let mut words = [0u8; 2];
+    spi.read_into(&mut words).unwrap();
+
+    // let the last frame and any stray edge through
+    cortex_m::asm::delay(100_000);
+    cortex_m::peripheral::NVIC::mask(Interrupt::EXTI9_5);
+
+    let edges = SCK_EDGES.load(Ordering::Relaxed);
+    hprintln!("{} rising SCK edges", edges).ok();
+    assert!(edges >= 32, "frames missing");
+    assert_eq!(edges % 8, 0, "stray SCK edge around a direction switch");
+
+    // NOTE(unsafe) read only access to a configuration register
+    let cr1 = unsafe { (*pac::SPI1::ptr()).cr1.read() };
+    assert!(cr1.bidioe().bit_is_set(), "data line not an output again");
+    assert!(cr1.spe().bit_is_set(), "SPI not enabled again");
+
+    hprintln!("direction switches are glitch free").ok();
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
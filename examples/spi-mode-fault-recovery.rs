@@ -0,0 +1,63 @@
+//! Checks on the target that `Spi::recover_from_mode_fault` makes the bus a master again.
+//!
+//! SPI1 on PA5 (SCK), PA6 (MISO) and PA7 (MOSI) raises a mode fault by driving the internal
+//! NSS level low, which has the hardware clear MSTR and SPE. After the level is set high again
+//! and the fault recovered, MODF has to be clear, MSTR and SPE set, and a transfer has to work.
+//! The result is printed over semihosting, a mismatch panics.
+
+#![no_main]
+#![no_std]
+
+use panic_semihosting as _;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use stm32f4xx_hal::{pac, prelude::*, spi::*};
+
+/// Returns the `MSTR` and `SPE` bits of CR1
+fn master_enabled() -> (bool, bool) {
+    // NOTE(unsafe) read only access to a configuration register
+    let cr1 = unsafe { (*pac::SPI1::ptr()).cr1.read() };
+    (cr1.mstr().bit_is_set(), cr1.spe().bit_is_set())
+}
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.freeze();
+
+    let gpioa = dp.GPIOA.split();
+    let sck = gpioa.pa5.into_alternate();
+    let miso = gpioa.pa6.into_alternate();
+    let mosi = gpioa.pa7.into_alternate();
+
+    let mode = Mode {
+        polarity: Polarity::IdleLow,
+        phase: Phase::CaptureOnFirstTransition,
+    };
+    let mut spi = Spi::new(dp.SPI1, (sck, miso, mosi), mode, 1_000_000.hz(), &clocks);
+    assert_eq!(master_enabled(), (true, true), "not a master after init");
+
+    spi.set_internal_nss(false);
+    assert!(spi.is_modf(), "no mode fault raised");
+    assert_eq!(
+        master_enabled(),
+        (false, false),
+        "MSTR and SPE not cleared by the fault"
+    );
+
+    spi.set_internal_nss(true);
+    spi.recover_from_mode_fault();
+    assert!(!spi.is_modf(), "MODF still set");
+    assert_eq!(master_enabled(), (true, true), "MSTR and SPE not set again");
+
+    let mut words = [0x12u8, 0x34];
+    spi.transfer(&mut words).unwrap();
+
+    hprintln!("master mode restored after a mode fault").ok();
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
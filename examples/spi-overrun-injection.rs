@@ -0,0 +1,56 @@
+//! Checks on the target that an injected overrun drains RX with 16-bit frames.
+//!
+//! SPI1 on PA5 (SCK), PA6 (MISO) and PA7 (MOSI) runs with 16-bit frames. A frame is left
+//! unread in DR, then an overrun is injected with `Spi::inject_error`. The next `send` has to
+//! report `Error::Overrun` and leave RX empty, and the following transfer has to work again.
+//! The result is printed over semihosting, a mismatch panics.
+
+#![no_main]
+#![no_std]
+
+use panic_semihosting as _;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use stm32f4xx_hal::{pac, prelude::*, spi::*};
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.freeze();
+
+    let gpioa = dp.GPIOA.split();
+    let sck = gpioa.pa5.into_alternate();
+    let miso = gpioa.pa6.into_alternate();
+    let mosi = gpioa.pa7.into_alternate();
+
+    let mode = Mode {
+        polarity: Polarity::IdleLow,
+        phase: Phase::CaptureOnFirstTransition,
+    };
+    let mut spi =
+        Spi::new(dp.SPI1, (sck, miso, mosi), mode, 1_000_000.hz(), &clocks).with_u16_data();
+
+    // leave a received frame in DR
+    nb::block!(spi.send(0xa5a5)).unwrap();
+    while !spi.is_rxne() {}
+
+    spi.inject_error(Error::Overrun);
+    assert_eq!(
+        spi.send(0x5a5a),
+        Err(nb::Error::Other(Error::Overrun)),
+        "injected overrun not reported"
+    );
+    assert!(!spi.is_rxne(), "RX not drained");
+    assert!(!spi.is_ovr(), "OVR still set");
+
+    let mut words = [0x1234u16, 0x5678];
+    spi.transfer(&mut words).unwrap();
+
+    hprintln!("overrun drained, bus usable again").ok();
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
@@ -0,0 +1,51 @@
+//! Checks on the target that `Spi::set_frame_format` switches the FRF bit of CR2.
+//!
+//! SPI1 on PA5 (SCK), PA6 (MISO) and PA7 (MOSI) is switched to the TI frame format and back,
+//! and the bit is read back after each step. The result is printed over semihosting, a
+//! mismatch panics.
+
+#![no_main]
+#![no_std]
+
+use panic_semihosting as _;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use stm32f4xx_hal::{pac, prelude::*, spi::*};
+
+/// Returns `true` if FRF selects the TI frame format
+fn frf_is_ti() -> bool {
+    // NOTE(unsafe) read only access to a configuration register
+    unsafe { (*pac::SPI1::ptr()).cr2.read().frf().bit_is_set() }
+}
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.freeze();
+
+    let gpioa = dp.GPIOA.split();
+    let sck = gpioa.pa5.into_alternate();
+    let miso = gpioa.pa6.into_alternate();
+    let mosi = gpioa.pa7.into_alternate();
+
+    let mode = Mode {
+        polarity: Polarity::IdleLow,
+        phase: Phase::CaptureOnFirstTransition,
+    };
+    let mut spi = Spi::new(dp.SPI1, (sck, miso, mosi), mode, 1_000_000.hz(), &clocks);
+    assert!(!frf_is_ti(), "Motorola format after reset");
+
+    spi.set_frame_format(FrameFormat::Ti);
+    assert!(frf_is_ti(), "TI format not selected");
+
+    spi.set_frame_format(FrameFormat::Motorola);
+    assert!(!frf_is_ti(), "Motorola format not restored");
+
+    hprintln!("FRF follows the frame format").ok();
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
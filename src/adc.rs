@@ -8,10 +8,15 @@
     Temperature in °C = (110-30) * (adc_sample - VtempCal30::get().read()) / (VtempCal110::get().read()-VtempCal30::get().read()) + 30
 */
 
-use crate::dma::traits::PeriAddress;
+use crate::dma::{
+    config::DmaConfig,
+    traits::{Channel as DmaChannel, DMASet, PeriAddress, Stream},
+    ChannelX, PeripheralToMemory, Transfer,
+};
 use crate::rcc::{Enable, Reset};
 use crate::{gpio::*, pac, signature::VrefCal, signature::VDDA_CALIB};
 use core::fmt;
+use embedded_dma::StaticWriteBuffer;
 use embedded_hal::adc::{Channel, OneShot};
 
 /// Vref internal signal, used for calibration
@@ -23,6 +28,15 @@ pub struct Vbat;
 /// Core temperature internal signal
 pub struct Temperature;
 
+/// Pin or internal signal connected to an ADC channel
+///
+/// Implemented only for the pins actually routed to `ADC`, so the channel is
+/// resolved at compile time and passing a wrong pin fails to compile.
+pub trait AnalogChannel<ADC> {
+    /// Channel number of the pin on `ADC`
+    const CHANNEL: u8;
+}
+
 macro_rules! adc_pins {
     ($($pin:ty => ($adc:ident, $chan:expr)),+ $(,)*) => {
         $(
@@ -30,6 +44,10 @@ macro_rules! adc_pins {
                 type ID = u8;
                 fn channel() -> u8 { $chan }
             }
+
+            impl AnalogChannel<pac::$adc> for $pin {
+                const CHANNEL: u8 = $chan;
+            }
         )+
     };
 }
@@ -249,6 +267,12 @@ pub mod config {
         Tim_5_cc_2,
         /// TIM5 compare channel 3
         Tim_5_cc_3,
+        /// TIM8 compare channel 1
+        #[cfg(feature = "tim8")]
+        Tim_8_cc_1,
+        /// TIM8 trigger out
+        #[cfg(feature = "tim8")]
+        Tim_8_trgo,
         /// External interupt line 11
         Exti_11,
     }
@@ -268,6 +292,10 @@ pub mod config {
                 ExternalTrigger::Tim_5_cc_1 => 0b1010,
                 ExternalTrigger::Tim_5_cc_2 => 0b1011,
                 ExternalTrigger::Tim_5_cc_3 => 0b1100,
+                #[cfg(feature = "tim8")]
+                ExternalTrigger::Tim_8_cc_1 => 0b1101,
+                #[cfg(feature = "tim8")]
+                ExternalTrigger::Tim_8_trgo => 0b1110,
                 ExternalTrigger::Exti_11 => 0b1111,
             }
         }
@@ -384,6 +412,8 @@ pub mod config {
         pub(crate) end_of_conversion_interrupt: Eoc,
         pub(crate) default_sample_time: SampleTime,
         pub(crate) vdda: Option<u32>,
+        pub(crate) offset: i32,
+        pub(crate) gain: u32,
     }
 
     impl AdcConfig {
@@ -445,6 +475,17 @@ pub mod config {
             self.vdda = Some(vdda_mv);
             self
         }
+
+        /// Specify the software calibration applied by `Adc::read_calibrated`.
+        ///
+        /// # Args
+        /// * `offset` - Raw sample value read with the input at ground.
+        /// * `gain` - Correction factor in 16.16 fixed point, `0x1_0000` is a gain of 1.
+        pub fn calibration(mut self, offset: i32, gain: u32) -> Self {
+            self.offset = offset;
+            self.gain = gain;
+            self
+        }
     }
 
     impl Default for AdcConfig {
@@ -460,6 +501,8 @@ pub mod config {
                 end_of_conversion_interrupt: Eoc::Disabled,
                 default_sample_time: SampleTime::Cycles_480,
                 vdda: None,
+                offset: 0,
+                gain: 0x1_0000,
             }
         }
     }
@@ -624,6 +667,15 @@ macro_rules! adc {
         /// Calculates the system VDDA by sampling the internal VREF channel and comparing
         /// the result with the value stored at the factory.
         pub fn calibrate(&mut self) {
+            self.calibrated_vdda = u32::from(self.read_vdda_millivolts());
+        }
+
+        /// Measures VDDA in millivolts, e.g. to monitor a battery supplying the MCU directly.
+        ///
+        /// The internal VREF channel is sampled and compared with its factory calibration, which
+        /// was taken at a VDDA of 3.3 V. Unlike [`calibrate`](Self::calibrate) this leaves the VDDA
+        /// used by `sample_to_millivolts` unchanged.
+        pub fn read_vdda_millivolts(&mut self) -> u16 {
             self.enable();
 
             let vref_en = self.temperature_and_vref_enabled();
@@ -633,11 +685,13 @@ macro_rules! adc {
 
             let vref_cal = VrefCal::get().read();
             let vref_samp = self.read(&mut Vref).unwrap(); //This can't actually fail, it's just in a result to satisfy hal trait
+            // the calibration value is a 12 bit sample
+            let vref_samp = (u32::from(vref_samp) * 4095 / self.max_sample).max(1);
 
-            self.calibrated_vdda = (VDDA_CALIB * u32::from(vref_cal)) / u32::from(vref_samp);
             if !vref_en {
                 self.disable_temperature_and_vref();
             }
+            ((VDDA_CALIB * u32::from(vref_cal)) / vref_samp) as u16
         }
 
         /// Enables the vbat internal channel
@@ -899,6 +953,11 @@ macro_rules! adc {
                 where
                     CHANNEL: Channel<pac::$adc_type, ID=u8>
                 {
+                    self.configure_channel_id(CHANNEL::channel(), sequence, sample_time);
+                }
+
+                /// Configures the channel number `channel` for sampling, see [`configure_channel`](Self::configure_channel)
+                fn configure_channel_id(&mut self, channel: u8, sequence: config::Sequence, sample_time: config::SampleTime) {
                     //Check the sequence is long enough
                     self.adc_reg.sqr1.modify(|r, w| {
                         let prev: config::Sequence = r.l().bits().into();
@@ -909,8 +968,6 @@ macro_rules! adc {
                         }
                     });
 
-                    let channel = CHANNEL::channel();
-
                     //Set the channel in the right sequence field
                     match sequence {
                         config::Sequence::One      => self.adc_reg.sqr3.modify(|_, w| unsafe {w.sq1().bits(channel) }),
@@ -973,10 +1030,15 @@ macro_rules! adc {
 
                 /// Synchronously convert a single sample
                 /// Note that it reconfigures the adc sequence and doesn't restore it
-                pub fn convert<PIN>(&mut self, pin: &PIN, sample_time: config::SampleTime) -> u16
+                pub fn convert<PIN>(&mut self, _pin: &PIN, sample_time: config::SampleTime) -> u16
                 where
                     PIN: Channel<pac::$adc_type, ID=u8>
                 {
+                    self.convert_channel_id(PIN::channel(), sample_time)
+                }
+
+                /// Synchronously converts a single sample of the channel number `channel`, see [`convert`](Self::convert)
+                fn convert_channel_id(&mut self, channel: u8, sample_time: config::SampleTime) -> u16 {
                     self.adc_reg.cr2.modify(|_, w| w
                         .dma().clear_bit() //Disable dma
                         .cont().clear_bit() //Disable continuous mode
@@ -989,7 +1051,7 @@ macro_rules! adc {
                     );
 
                     self.reset_sequence();
-                    self.configure_channel(pin, config::Sequence::One, sample_time);
+                    self.configure_channel_id(channel, config::Sequence::One, sample_time);
                     self.enable();
                     self.clear_end_of_conversion_flag();
                     self.start_conversion();
@@ -1004,6 +1066,129 @@ macro_rules! adc {
 
                     result
                 }
+
+                /// Synchronously convert a single sample of `pin` using the default sample time
+                ///
+                /// The channel is taken from the [`AnalogChannel`] implementation of the pin, so a
+                /// pin that isn't routed to this ADC fails to compile.
+                pub fn read_channel<PIN>(&mut self, _pin: &PIN) -> u16
+                where
+                    PIN: AnalogChannel<pac::$adc_type>
+                {
+                    let enabled = self.is_enabled();
+                    if !enabled {
+                        self.enable();
+                    }
+
+                    let sample = self.convert_channel_id(PIN::CHANNEL, self.config.default_sample_time);
+
+                    if !enabled {
+                        self.disable();
+                    }
+
+                    sample
+                }
+
+                /// Converts `pin` once per element of `buf` in a single DMA burst on `stream` and
+                /// returns the average, together with the adc, the stream and the buffer
+                ///
+                /// The conversions run back to back in continuous mode and the stream moves each
+                /// result to `buf`, the average is computed once the stream reports transfer
+                /// complete. Uncorrelated noise drops by the square root of the buffer length.
+                /// Like [`convert`](Self::convert) this reconfigures the adc sequence, the
+                /// configuration is restored afterwards.
+                ///
+                /// ```ignore
+                /// let buf = cortex_m::singleton!(: [u16; 16] = [0; 16]).unwrap();
+                /// let (average, adc, stream, buf) = adc.read_averaged(&pin, dma2.0, buf);
+                /// ```
+                ///
+                /// # Panics
+                ///
+                /// If `buf` is empty.
+                pub fn read_averaged<PIN, STREAM, BUF, const CHANNEL: u8>(
+                    mut self,
+                    _pin: &PIN,
+                    stream: STREAM,
+                    buf: BUF,
+                ) -> (u16, Self, STREAM, BUF)
+                where
+                    PIN: AnalogChannel<pac::$adc_type>,
+                    STREAM: Stream,
+                    ChannelX<CHANNEL>: DmaChannel,
+                    Self: DMASet<STREAM, PeripheralToMemory, CHANNEL>,
+                    BUF: StaticWriteBuffer<Word = u16> + AsRef<[u16]>,
+                {
+                    assert!(!buf.as_ref().is_empty(), "no room for a sample");
+                    let enabled = self.is_enabled();
+
+                    self.adc_reg.cr2.modify(|_, w| w
+                        .cont().set_bit() //Convert back to back
+                        .exten().bits(config::TriggerMode::Disabled.into()) //Disable trigger
+                        .dds().clear_bit() //No DMA request after the last transfer of the stream
+                        .dma().set_bit() //Enable dma
+                    );
+                    self.adc_reg.cr1.modify(|_, w| w
+                        .scan().clear_bit() //Disable scan mode
+                        .eocie().clear_bit() //Disable end of conversion interrupt
+                    );
+
+                    self.reset_sequence();
+                    let sample_time = self.config.default_sample_time;
+                    self.configure_channel_id(PIN::CHANNEL, config::Sequence::One, sample_time);
+
+                    let config = DmaConfig::default().memory_increment(true);
+                    let mut transfer = Transfer::init_peripheral_to_memory(stream, self, buf, None, config);
+                    transfer.start(|adc| adc.start_conversion());
+                    while !STREAM::get_transfer_complete_flag() {}
+                    let (stream, mut adc, buf, _) = transfer.release();
+
+                    //Stop the conversions, the ones after the burst overrun the data register
+                    adc.adc_reg.cr2.modify(|_, w| w.cont().clear_bit().dma().clear_bit());
+                    adc.adc_reg.sr.modify(|_, w| w.ovr().clear_bit().strt().clear_bit());
+
+                    //Reset the config
+                    adc.apply_config(adc.config);
+                    if !enabled {
+                        adc.disable();
+                    }
+
+                    let samples = buf.as_ref();
+                    let sum: u32 = samples.iter().map(|&sample| u32::from(sample)).sum();
+                    let average = (sum / samples.len() as u32) as u16;
+                    (average, adc, stream, buf)
+                }
+
+                /// Synchronously convert a single sample of `pin` and apply the software calibration
+                ///
+                /// Returns `(raw - offset) * gain` with the offset and gain set in the config
+                /// or by [`calibrate_offset`](Self::calibrate_offset).
+                pub fn read_calibrated<PIN>(&mut self, pin: &PIN) -> i32
+                where
+                    PIN: AnalogChannel<pac::$adc_type>
+                {
+                    let raw = i64::from(self.read_channel(pin));
+                    let offset = i64::from(self.config.offset);
+                    let gain = i64::from(self.config.gain);
+                    (((raw - offset) * gain) >> 16) as i32
+                }
+
+                /// Measures the offset on `pin`, which must be connected to ground, by averaging
+                /// `samples` conversions. The result is stored for `read_calibrated` and returned.
+                pub fn calibrate_offset<PIN>(&mut self, pin: &PIN, samples: u16) -> i32
+                where
+                    PIN: AnalogChannel<pac::$adc_type>
+                {
+                    let samples = samples.max(1);
+                    let sum: u32 = (0..samples).map(|_| u32::from(self.read_channel(pin))).sum();
+                    self.config.offset = (sum / u32::from(samples)) as i32;
+                    self.config.offset
+                }
+
+                /// Sets the software calibration gain in 16.16 fixed point
+                pub fn set_calibration_gain(&mut self, gain: u32) {
+                    self.config.gain = gain;
+                }
             }
 
             impl<PIN> OneShot<pac::$adc_type, u16, PIN> for Adc<pac::$adc_type>
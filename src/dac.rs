@@ -25,6 +25,31 @@ pub trait DacPin {
     fn enable(&mut self);
 }
 
+/// Simultaneous update of both DAC channels
+pub trait DualDacOut {
+    /// Writes both channels through the dual holding register (`DHR12RD`)
+    ///
+    /// Without triggers both outputs change in the same cycle. With triggers enabled on both
+    /// channels and the same trigger source selected, both change on the same trigger event.
+    fn set_both(&mut self, ch1: u16, ch2: u16);
+
+    /// Fires the software trigger of both channels at once
+    fn trigger_both(&mut self);
+}
+
+impl DualDacOut for (C1, C2) {
+    fn set_both(&mut self, ch1: u16, ch2: u16) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        dac.dhr12rd
+            .write(|w| unsafe { w.bits(u32::from(ch2) << 16 | u32::from(ch1)) });
+    }
+
+    fn trigger_both(&mut self) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        dac.swtrigr.write(|w| unsafe { w.bits(0b11) });
+    }
+}
+
 pub trait Pins<DAC> {
     type Output;
     #[doc(hidden)]
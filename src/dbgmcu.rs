@@ -0,0 +1,155 @@
+//! Debug support (DBGMCU)
+//!
+//! Controls how peripherals behave while the core is halted by a debugger, and whether the
+//! debug connection is kept in the low-power modes.
+//!
+//! ```ignore
+//! let mut dbg = DebugControl::new(dp.DBGMCU);
+//! dbg.stop_on_halt::<pac::TIM2>(true);
+//! dbg.stop_on_halt::<pac::IWDG>(true);
+//! ```
+
+use crate::pac::{self, DBGMCU};
+
+/// Peripherals that can be frozen while the core is halted
+pub trait StopOnHalt: crate::Sealed {
+    #[doc(hidden)]
+    const APB2: bool;
+    #[doc(hidden)]
+    const BIT: u8;
+}
+
+/// Wraps the DBGMCU peripheral
+pub struct DebugControl {
+    dbgmcu: DBGMCU,
+}
+
+impl DebugControl {
+    pub fn new(dbgmcu: DBGMCU) -> Self {
+        Self { dbgmcu }
+    }
+
+    /// Stops the counter of a timer, the watchdogs, the RTC or the CAN controllers, or the
+    /// SMBus timeout of an I2C peripheral, while the core is halted
+    pub fn stop_on_halt<P: StopOnHalt>(&mut self, stop: bool) {
+        let mask = 1 << P::BIT;
+        if P::APB2 {
+            self.dbgmcu.apb2_fz.modify(|r, w| unsafe {
+                w.bits(if stop {
+                    r.bits() | mask
+                } else {
+                    r.bits() & !mask
+                })
+            });
+        } else {
+            self.dbgmcu.apb1_fz.modify(|r, w| unsafe {
+                w.bits(if stop {
+                    r.bits() | mask
+                } else {
+                    r.bits() & !mask
+                })
+            });
+        }
+    }
+
+    /// Keeps the debug connection alive in Sleep mode
+    pub fn debug_in_sleep(&mut self, enable: bool) {
+        self.dbgmcu.cr.modify(|_, w| w.dbg_sleep().bit(enable));
+    }
+
+    /// Keeps the debug connection alive in Stop mode
+    pub fn debug_in_stop(&mut self, enable: bool) {
+        self.dbgmcu.cr.modify(|_, w| w.dbg_stop().bit(enable));
+    }
+
+    /// Keeps the debug connection alive in Standby mode
+    pub fn debug_in_standby(&mut self, enable: bool) {
+        self.dbgmcu.cr.modify(|_, w| w.dbg_standby().bit(enable));
+    }
+
+    /// Releases the DBGMCU peripheral
+    pub fn release(self) -> DBGMCU {
+        self.dbgmcu
+    }
+}
+
+impl crate::Sealed for pac::RTC {}
+impl crate::Sealed for pac::WWDG {}
+impl crate::Sealed for pac::IWDG {}
+
+macro_rules! stop_on_halt {
+    ($($PER:ident: $apb2:literal, $bit:literal;)+) => {
+        $(
+            impl StopOnHalt for pac::$PER {
+                const APB2: bool = $apb2;
+                const BIT: u8 = $bit;
+            }
+        )+
+    };
+}
+
+stop_on_halt! {
+    TIM5: false, 3;
+    RTC: false, 10;
+    WWDG: false, 11;
+    IWDG: false, 12;
+    I2C1: false, 21;
+    I2C2: false, 22;
+    TIM1: true, 0;
+    TIM9: true, 16;
+    TIM11: true, 18;
+}
+
+#[cfg(feature = "tim2")]
+stop_on_halt! {
+    TIM2: false, 0;
+    TIM3: false, 1;
+    TIM4: false, 2;
+    TIM10: true, 17;
+}
+
+// All parts except F401 and F411.
+#[cfg(any(
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f410",
+    feature = "stm32f412",
+    feature = "stm32f413",
+    feature = "stm32f415",
+    feature = "stm32f417",
+    feature = "stm32f423",
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f446",
+    feature = "stm32f469",
+    feature = "stm32f479"
+))]
+stop_on_halt! {
+    TIM6: false, 4;
+}
+
+#[cfg(feature = "tim8")]
+stop_on_halt! {
+    TIM7: false, 5;
+    TIM12: false, 6;
+    TIM13: false, 7;
+    TIM14: false, 8;
+    TIM8: true, 1;
+}
+
+#[cfg(feature = "i2c3")]
+stop_on_halt! {
+    I2C3: false, 23;
+}
+
+#[cfg(feature = "can1")]
+stop_on_halt! {
+    CAN1: false, 25;
+}
+
+#[cfg(feature = "can2")]
+stop_on_halt! {
+    CAN2: false, 26;
+}
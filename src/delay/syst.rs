@@ -41,9 +41,11 @@ impl DelayMs<u8> for Delay<SYST> {
 impl DelayUs<u32> for Delay<SYST> {
     fn delay_us(&mut self, us: u32) {
         // The SysTick Reload Value register supports values between 1 and 0x00FFFFFF.
-        const MAX_RVR: u32 = 0x00FF_FFFF;
+        const MAX_RVR: u64 = 0x00FF_FFFF;
 
-        let mut total_rvr = us * (self.clk.0 / 8_000_000);
+        // SysTick runs at HCLK / 8; round up so that clocks which aren't a whole
+        // multiple of 8 MHz never shorten the delay.
+        let mut total_rvr = (u64::from(us) * u64::from(self.clk.0) + 7_999_999) / 8_000_000;
 
         while total_rvr != 0 {
             let current_rvr = if total_rvr <= MAX_RVR {
@@ -52,7 +54,7 @@ impl DelayUs<u32> for Delay<SYST> {
                 MAX_RVR
             };
 
-            self.tim.set_reload(current_rvr);
+            self.tim.set_reload(current_rvr as u32);
             self.tim.clear_current();
             self.tim.enable_counter();
 
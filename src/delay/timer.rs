@@ -5,13 +5,12 @@
 
 use core::cmp::max;
 
-use cast::{u16, u32};
 use embedded_hal::blocking::delay::{DelayMs, DelayUs};
 
 use crate::{
     pac,
     rcc::Clocks,
-    timer::{General, Timer},
+    timer::{split_ticks, General, Instance, Timer},
 };
 
 use super::Delay;
@@ -57,72 +56,53 @@ macro_rules! hal {
                 pub fn $tim(tim: $TIM, clocks: &Clocks) -> Self {
                     Timer::new(tim, clocks).delay()
                 }
+
+                /// Blocks for `time / unit` seconds, rounded up to whole timer clock cycles
+                ///
+                /// Counting timer clock cycles keeps the delay exact when the clock isn't a
+                /// whole number of MHz. Delays longer than one pulse at the largest prescaler
+                /// are split into several pulses.
+                fn delay_ticks(&mut self, time: u64, unit: u64) {
+                    // longest timeout of one pulse, in timer clock cycles
+                    let max_ticks = (1 << 16) * u64::from(<$TIM as Instance>::MAX_AUTO_RELOAD);
+
+                    let mut ticks = (time * u64::from(self.clk.0) + unit - 1) / unit;
+                    while ticks > 0 {
+                        let chunk = ticks.min(max_ticks);
+                        ticks -= chunk;
+
+                        let (psc, arr) =
+                            split_ticks(chunk, <$TIM as Instance>::MAX_AUTO_RELOAD).unwrap();
+                        $waitfn(&mut self.tim, psc, arr);
+                    }
+                }
             }
 
             impl DelayUs<u32> for Delay<$TIM> {
                 /// Sleep for up to 2^32-1 microseconds (~71 minutes).
                 fn delay_us(&mut self, us: u32) {
-                    // Set up prescaler so that a tick takes exactly 1 µs.
-                    //
-                    // For example, if the clock is set to 48 MHz, with a prescaler of 48
-                    // we'll get ticks that are 1 µs long. This means that we can write the
-                    // delay value directly to the auto-reload register (ARR).
-                    let psc = u16(self.clk.0 / 1_000_000)
-                        .expect("Prescaler does not fit in u16");
-                    let arr = us;
-                    $waitfn(&mut self.tim, psc, arr);
+                    self.delay_ticks(u64::from(us), 1_000_000);
                 }
             }
 
             impl DelayUs<u16> for Delay<$TIM> {
                 /// Sleep for up to 2^16-1 microseconds (~65 milliseconds).
                 fn delay_us(&mut self, us: u16) {
-                    // See DelayUs<u32> for explanations.
-                    let psc = u16(self.clk.0 / 1_000_000)
-                        .expect("Prescaler does not fit in u16");
-                    let arr = u32(us);
-                    $waitfn(&mut self.tim, psc, arr);
+                    self.delay_ticks(u64::from(us), 1_000_000);
                 }
             }
 
             impl DelayMs<u32> for Delay<$TIM> {
-                /// Sleep for up to (2^32)/2-1 milliseconds (~24 days).
-                /// If the `ms` value is larger than 2147483647, the code will panic.
+                /// Sleep for up to 2^32-1 milliseconds (~49 days).
                 fn delay_ms(&mut self, ms: u32) {
-                    // See next section for explanation why the usable range is reduced.
-                    assert!(ms <= 2_147_483_647); // (2^32)/2-1
-
-                    // Set up prescaler so that a tick takes exactly 0.5 ms.
-                    //
-                    // For example, if the clock is set to 48 MHz, with a prescaler of 24'000
-                    // we'll get ticks that are 0.5 ms long. This means that we can write the
-                    // delay value multipled by two to the auto-reload register (ARR).
-                    //
-                    // Note that we cannot simply use a prescaler value where the tick corresponds
-                    // to 1 ms, because then a clock of 100 MHz would correspond to a prescaler
-                    // value of 100'000, which doesn't fit in the 16-bit PSC register.
-                    //
-                    // Unfortunately this means that only one half of the full 32-bit range
-                    // can be used, but 24 days should be plenty of usable delay time.
-                    let psc = u16(self.clk.0 / 1000 / 2)
-                        .expect("Prescaler does not fit in u16");
-
-                    // Since PSC = 0.5 ms, double the value for the ARR
-                    let arr = ms << 1;
-
-                    $waitfn(&mut self.tim, psc, arr);
+                    self.delay_ticks(u64::from(ms), 1_000);
                 }
             }
 
             impl DelayMs<u16> for Delay<$TIM> {
                 /// Sleep for up to (2^16)-1 milliseconds (~65 seconds).
                 fn delay_ms(&mut self, ms: u16) {
-                    // See DelayMs<u32> for explanations. Since the value range is only 16 bit,
-                    // we don't need an assert here.
-                    let psc = u16(self.clk.0 / 1000 / 2)
-                        .expect("Prescaler does not fit in u16");
-                    let arr = u32(ms) << 1;
-                    $waitfn(&mut self.tim, psc, arr);
+                    self.delay_ticks(u64::from(ms), 1_000);
                 }
             }
         )+
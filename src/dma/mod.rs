@@ -751,8 +751,8 @@ pub mod config {
     #[derive(Debug, Clone, Copy)]
     pub struct DmaConfig {
         pub(crate) priority: Priority,
-        pub(crate) memory_increment: bool,
-        pub(crate) peripheral_increment: bool,
+        pub(crate) memory_increment: Option<bool>,
+        pub(crate) peripheral_increment: Option<bool>,
         pub(crate) transfer_complete_interrupt: bool,
         pub(crate) half_transfer_interrupt: bool,
         pub(crate) transfer_error_interrupt: bool,
@@ -769,8 +769,8 @@ pub mod config {
         fn default() -> Self {
             Self {
                 priority: Priority::Medium,
-                memory_increment: false,
-                peripheral_increment: false,
+                memory_increment: None,
+                peripheral_increment: None,
                 transfer_complete_interrupt: false,
                 half_transfer_interrupt: false,
                 transfer_error_interrupt: false,
@@ -794,15 +794,22 @@ pub mod config {
         }
 
         /// Set the memory_increment.
+        ///
+        /// If not set, the memory address is incremented for every transfer direction. Earlier
+        /// versions kept it fixed by default, pass `false` for a transfer from or to a single
+        /// memory location.
         #[inline(always)]
         pub fn memory_increment(mut self, memory_increment: bool) -> Self {
-            self.memory_increment = memory_increment;
+            self.memory_increment = Some(memory_increment);
             self
         }
         /// Set the peripheral_increment.
+        ///
+        /// If not set, the peripheral address is only incremented in memory to memory transfers,
+        /// where it points to the source buffer.
         #[inline(always)]
         pub fn peripheral_increment(mut self, peripheral_increment: bool) -> Self {
-            self.peripheral_increment = peripheral_increment;
+            self.peripheral_increment = Some(peripheral_increment);
             self
         }
         /// Set the transfer_complete_interrupt.
@@ -1312,6 +1319,38 @@ where
         self.stream.clear_half_transfer_interrupt();
     }
 
+    /// Enable/disable the half transfer interrupt (htie) of the DMA stream.
+    ///
+    /// Together with [`half_complete`](Self::half_complete) and
+    /// [`full_complete`](Self::full_complete) this allows processing one half of a circular
+    /// buffer while the DMA fills the other.
+    #[inline(always)]
+    pub fn enable_half_transfer_interrupt(&mut self, enable: bool) {
+        self.stream.set_half_transfer_interrupt_enable(enable);
+    }
+
+    /// Returns `true` and clears the half transfer flag (htif) if the first half of the buffer
+    /// has been transferred.
+    #[inline(always)]
+    pub fn half_complete(&mut self) -> bool {
+        let set = STREAM::get_half_transfer_flag();
+        if set {
+            self.stream.clear_half_transfer_interrupt();
+        }
+        set
+    }
+
+    /// Returns `true` and clears the transfer complete flag (tcif) if the whole buffer has been
+    /// transferred.
+    #[inline(always)]
+    pub fn full_complete(&mut self) -> bool {
+        let set = STREAM::get_transfer_complete_flag();
+        if set {
+            self.stream.clear_transfer_complete_interrupt();
+        }
+        set
+    }
+
     /// Clear transfer error interrupt (teif) for the DMA stream.
     #[inline(always)]
     pub fn clear_transfer_error_interrupt(&mut self) {
@@ -1352,8 +1391,9 @@ where
             stream.set_memory_size(msize as u8);
             stream.set_peripheral_size(msize as u8);
         }
-        stream.set_memory_increment(config.memory_increment);
-        stream.set_peripheral_increment(config.peripheral_increment);
+        let is_mem2mem = DIR::direction() == DmaDirection::MemoryToMemory;
+        stream.set_memory_increment(config.memory_increment.unwrap_or(true));
+        stream.set_peripheral_increment(config.peripheral_increment.unwrap_or(is_mem2mem));
         stream.set_transfer_complete_interrupt_enable(config.transfer_complete_interrupt);
         stream.set_half_transfer_interrupt_enable(config.half_transfer_interrupt);
         stream.set_transfer_error_interrupt_enable(config.transfer_error_interrupt);
@@ -285,6 +285,47 @@ pub trait Channel {}
 
 /// Trait to mark a set of Stream, Channel and Direction for a Peripheral as correct together.
 ///
+/// It is implemented for exactly the combinations of the DMA request mapping tables of the
+/// reference manual. As `Transfer` requires it, SPI1_TX can be wired to DMA2 stream 3,
+/// channel 3:
+///
+#[cfg_attr(feature = "device-selected", doc = "```no_run")]
+#[cfg_attr(not(feature = "device-selected"), doc = "```ignore")]
+/// # use stm32f4xx_hal::{dma::{config::DmaConfig, MemoryToPeripheral, StreamsTuple, Transfer}, pac};
+/// # use stm32f4xx_hal::spi::{Spi, TransferModeNormal};
+/// # fn f<PINS>(dma2: pac::DMA2, spi: Spi<pac::SPI1, PINS, TransferModeNormal>, buf: &'static mut [u8; 4]) {
+/// let streams = StreamsTuple::new(dma2);
+/// let tx = spi.use_dma().tx();
+/// let transfer = Transfer::<_, _, MemoryToPeripheral, _, 3>::init_memory_to_peripheral(
+///     streams.3,
+///     tx,
+///     buf,
+///     None,
+///     DmaConfig::default(),
+/// );
+/// # }
+/// ```
+///
+/// but the same code with a stream that never receives its requests fails to compile:
+///
+#[cfg_attr(feature = "device-selected", doc = "```compile_fail")]
+#[cfg_attr(not(feature = "device-selected"), doc = "```ignore")]
+/// # use stm32f4xx_hal::{dma::{config::DmaConfig, MemoryToPeripheral, StreamsTuple, Transfer}, pac};
+/// # use stm32f4xx_hal::spi::{Spi, TransferModeNormal};
+/// # fn f<PINS>(dma2: pac::DMA2, spi: Spi<pac::SPI1, PINS, TransferModeNormal>, buf: &'static mut [u8; 4]) {
+/// let streams = StreamsTuple::new(dma2);
+/// let tx = spi.use_dma().tx();
+/// // SPI1_TX is on DMA2 stream 3 or 5, channel 3, not on stream 0
+/// let transfer = Transfer::<_, _, MemoryToPeripheral, _, 3>::init_memory_to_peripheral(
+///     streams.0,
+///     tx,
+///     buf,
+///     None,
+///     DmaConfig::default(),
+/// );
+/// # }
+/// ```
+///
 /// # Safety
 ///
 /// Memory corruption might occur if this trait is implemented for an invalid combination.
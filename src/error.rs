@@ -0,0 +1,67 @@
+//! Common error type
+//!
+//! Wraps the errors of the communication peripherals, so an application can
+//! propagate all of them with `?` into a single error type.
+
+use crate::dma::DMAError;
+use crate::{i2c, serial, spi};
+
+/// Error of any communication peripheral
+#[non_exhaustive]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Error {
+    /// SPI error
+    Spi(spi::Error),
+    /// I2C error
+    I2c(i2c::Error),
+    /// Serial error
+    Serial(serial::Error),
+    /// DMA error, without the returned buffer
+    Dma(DmaError),
+}
+
+/// DMA error kind, see [`DMAError`]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum DmaError {
+    /// DMA not ready to change buffers.
+    NotReady,
+    /// The user provided a buffer that is not big enough while double buffering.
+    SmallBuffer,
+    /// Overrun during a double buffering or circular transfer.
+    Overrun,
+}
+
+impl From<spi::Error> for Error {
+    fn from(e: spi::Error) -> Self {
+        Self::Spi(e)
+    }
+}
+
+impl From<i2c::Error> for Error {
+    fn from(e: i2c::Error) -> Self {
+        Self::I2c(e)
+    }
+}
+
+impl From<serial::Error> for Error {
+    fn from(e: serial::Error) -> Self {
+        Self::Serial(e)
+    }
+}
+
+impl From<DmaError> for Error {
+    fn from(e: DmaError) -> Self {
+        Self::Dma(e)
+    }
+}
+
+/// Note that the buffer carried by the error is dropped
+impl<T> From<DMAError<T>> for Error {
+    fn from(e: DMAError<T>) -> Self {
+        Self::Dma(match e {
+            DMAError::NotReady(_) => DmaError::NotReady,
+            DMAError::SmallBuffer(_) => DmaError::SmallBuffer,
+            DMAError::Overrun(_) => DmaError::Overrun,
+        })
+    }
+}
@@ -0,0 +1,231 @@
+//! # Frequency and pulse counters
+//!
+//! Measures the frequency of a signal by counting its rising edges on channel 1 of one timer
+//! (external clock mode 1) while a second timer provides the gate window.
+//!
+//! ```ignore
+//! let gate = Timer::new(dp.TIM5, &clocks).count_down();
+//! let mut counter = FrequencyCounter::new(dp.TIM3, sensor_pin, gate);
+//! let freq = counter.measure(100.ms());
+//! ```
+//!
+//! A [`PulseCounter`] totals edges without a window instead. The update interrupt extends the
+//! counter to 64 bits, so it never saturates:
+//!
+//! ```ignore
+//! let mut flow = PulseCounter::new(dp.TIM3, flow_meter_pin);
+//!
+//! #[interrupt]
+//! fn TIM3() {
+//!     // the counter lives in a `Mutex<RefCell<Option<PulseCounter<..>>>>`
+//!     flow.on_overflow();
+//! }
+//!
+//! let litres = flow.total() / PULSES_PER_LITRE;
+//! ```
+use crate::{
+    pac::RCC,
+    rcc,
+    time::{Hertz, MilliSeconds},
+    timer::{CPin, CountDownTimer, General, C1},
+};
+
+/// Frequency counter built from a counting timer and a gate timer
+pub struct FrequencyCounter<TIM, PIN, GATE> {
+    tim: TIM,
+    pin: PIN,
+    gate: CountDownTimer<GATE>,
+}
+
+impl<TIM, PIN, GATE> FrequencyCounter<TIM, PIN, GATE>
+where
+    TIM: Instance,
+    PIN: CPin<C1, TIM>,
+    GATE: General,
+{
+    /// Configures `tim` to count rising edges on `pin` and uses `gate` for the measurement window
+    pub fn new(mut tim: TIM, pin: PIN, gate: CountDownTimer<GATE>) -> Self {
+        // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+        let rcc = unsafe { &(*RCC::ptr()) };
+        // Enable and reset clock.
+        TIM::enable(rcc);
+        TIM::reset(rcc);
+
+        tim.setup_external_clock();
+
+        Self { tim, pin, gate }
+    }
+
+    /// Counts input edges during `gate` and returns the resulting frequency
+    ///
+    /// Blocks for the duration of the gate window. The number of edges counted within one
+    /// window must fit the counter width (16 bits for most timers, 32 bits for TIM2 and TIM5).
+    /// A zero `gate` is clamped to 1 ms.
+    ///
+    /// # Panics
+    ///
+    /// If the gate timer can't time `gate`, see [`CountDownTimer::oneshot`].
+    pub fn measure(&mut self, gate: MilliSeconds) -> Hertz {
+        let gate = MilliSeconds(gate.0.max(1));
+        self.tim.reset_count();
+        self.gate.oneshot(gate).unwrap();
+        while !self.gate.poll_done() {}
+        let count = self.tim.read_count();
+
+        Hertz((u64::from(count) * 1_000 / u64::from(gate.0)) as u32)
+    }
+
+    /// Releases the counting timer, the input pin and the gate timer
+    pub fn release(self) -> (TIM, PIN, CountDownTimer<GATE>) {
+        (self.tim, self.pin, self.gate)
+    }
+}
+
+/// Counter of input edges, extended to 64 bits in software
+pub struct PulseCounter<TIM, PIN> {
+    tim: TIM,
+    pin: PIN,
+    overflows: u32,
+}
+
+impl<TIM, PIN> PulseCounter<TIM, PIN>
+where
+    TIM: Instance,
+    PIN: CPin<C1, TIM>,
+{
+    /// Configures `tim` to count rising edges on `pin`
+    ///
+    /// The update interrupt is enabled, its handler has to call
+    /// [`on_overflow`](Self::on_overflow).
+    pub fn new(mut tim: TIM, pin: PIN) -> Self {
+        // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+        let rcc = unsafe { &(*RCC::ptr()) };
+        // Enable and reset clock.
+        TIM::enable(rcc);
+        TIM::reset(rcc);
+
+        tim.setup_external_clock();
+        tim.take_overflow();
+        tim.listen_overflow(true);
+
+        Self {
+            tim,
+            pin,
+            overflows: 0,
+        }
+    }
+
+    /// Accounts for a counter overflow, to be called from the interrupt handler of the timer
+    pub fn on_overflow(&mut self) {
+        if self.tim.take_overflow() {
+            self.overflows = self.overflows.wrapping_add(1);
+        }
+    }
+
+    /// Returns the number of edges counted since creation or the last [`reset`](Self::reset)
+    ///
+    /// Call this with the timer interrupt masked, e.g. from within the critical section that
+    /// shares the counter with the interrupt handler.
+    pub fn total(&self) -> u64 {
+        let mut overflows = u64::from(self.overflows);
+        let mut count = self.tim.read_count();
+        if self.tim.overflow_pending() {
+            // the count may have been read before the overflow, read it again after it
+            count = self.tim.read_count();
+            overflows += 1;
+        }
+
+        overflows * (u64::from(TIM::MAX_COUNT) + 1) + u64::from(count)
+    }
+
+    /// Restarts counting from zero
+    pub fn reset(&mut self) {
+        self.tim.reset_count();
+        self.tim.take_overflow();
+        self.overflows = 0;
+    }
+
+    /// Stops the update interrupt and releases the timer and the input pin
+    pub fn release(mut self) -> (TIM, PIN) {
+        self.tim.listen_overflow(false);
+        (self.tim, self.pin)
+    }
+}
+
+pub trait Instance: crate::Sealed + rcc::Enable + rcc::Reset {
+    /// Largest value of the counter
+    const MAX_COUNT: u32;
+
+    fn setup_external_clock(&mut self);
+    fn reset_count(&mut self);
+    fn read_count(&self) -> u32;
+    fn listen_overflow(&mut self, listen: bool);
+    fn overflow_pending(&self) -> bool;
+    /// Returns whether the counter overflowed and clears the flag
+    fn take_overflow(&mut self) -> bool;
+}
+
+macro_rules! hal {
+    ($($TIM:ty: ($bits:ident),)+) => {
+        $(
+            impl Instance for $TIM {
+                const MAX_COUNT: u32 = $bits::MAX as u32;
+
+                fn setup_external_clock(&mut self) {
+                    // Configure TxC1 as input mapped on TI1, without filter
+                    self.ccmr1_output().write(|w| unsafe { w.cc1s().bits(0b01) });
+                    // count on rising edges
+                    self.ccer.write(|w| w.cc1p().clear_bit().cc1np().clear_bit());
+                    // external clock mode 1, trigger on filtered timer input 1 (TI1FP1)
+                    // some chip variants declare `.bits()` as unsafe, some don't
+                    #[allow(unused_unsafe)]
+                    self.smcr.write(|w| unsafe { w.sms().bits(0b111).ts().bits(0b101) });
+                    #[allow(unused_unsafe)]
+                    self.arr.write(|w| unsafe { w.bits($bits::MAX as u32) });
+                    self.cr1.write(|w| w.cen().set_bit());
+                }
+
+                fn reset_count(&mut self) {
+                    self.cnt.reset();
+                }
+
+                fn read_count(&self) -> u32 {
+                    self.cnt.read().bits()
+                }
+
+                fn listen_overflow(&mut self, listen: bool) {
+                    self.dier.modify(|_, w| w.uie().bit(listen));
+                }
+
+                fn overflow_pending(&self) -> bool {
+                    self.sr.read().uif().bit_is_set()
+                }
+
+                fn take_overflow(&mut self) -> bool {
+                    let pending = self.overflow_pending();
+                    if pending {
+                        self.sr.modify(|_, w| w.uif().clear_bit());
+                    }
+                    pending
+                }
+            }
+        )+
+    }
+}
+
+hal! {
+    crate::pac::TIM1: (u16),
+    crate::pac::TIM5: (u32),
+}
+
+#[cfg(feature = "tim2")]
+hal! {
+    crate::pac::TIM2: (u32),
+    crate::pac::TIM3: (u16),
+    crate::pac::TIM4: (u16),
+}
+
+#[cfg(feature = "tim8")]
+hal! {
+    crate::pac::TIM8: (u16),
+}
@@ -45,13 +45,14 @@
 
 use core::convert::Infallible;
 use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU16, Ordering};
 
 pub use embedded_hal::digital::v2::PinState;
 use embedded_hal::digital::v2::{
     InputPin, IoPin, OutputPin, StatefulOutputPin, ToggleableOutputPin,
 };
 
-use crate::pac::EXTI;
+use crate::pac::{EXTI, PWR};
 use crate::syscfg::SysCfg;
 
 mod alt;
@@ -62,6 +63,14 @@ mod partially_erased;
 pub use partially_erased::{PEPin, PartiallyErasedPin};
 mod erased;
 pub use erased::{EPin, ErasedPin};
+mod dynamic;
+pub use dynamic::{Dynamic, DynamicPin, PinModeError};
+mod matrix;
+pub use matrix::Matrix;
+mod port;
+pub use port::{EightBitOutputPort, FourBitInputPort, FourBitOutputPort, WholePort};
+mod board;
+pub use board::{configure_pins, PinConfig, PinConfigMode, Pull};
 
 /// A filler pin type
 pub struct NoPin;
@@ -141,6 +150,13 @@ pub enum Speed {
     VeryHigh = 3,
 }
 
+/// GPIO output driver type
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OutputType {
+    PushPull = 0,
+    OpenDrain = 1,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Edge {
     Rising,
@@ -151,15 +167,82 @@ pub enum Edge {
 mod sealed {
     /// Marker trait that show if `ExtiPin` can be implemented
     pub trait Interruptable {}
+
+    /// Keeps `ExtiPin` implemented by the pins of this crate only, so that methods can be
+    /// added to it
+    pub trait Sealed {}
+
+    /// Pull resistors that keep a wakeup input at a defined idle level
+    pub trait WakeupPull {
+        /// Edge leaving the idle level
+        const EDGE: super::Edge;
+    }
 }
 
 use sealed::Interruptable;
+impl<PIN> sealed::Sealed for PIN
+where
+    PIN: PinExt,
+    PIN::Mode: Interruptable,
+{
+}
+pub(crate) use sealed::WakeupPull;
 impl<MODE> Interruptable for Output<MODE> {}
 impl<MODE> Interruptable for Input<MODE> {}
+impl WakeupPull for PullUp {
+    const EDGE: Edge = Edge::Falling;
+}
+impl WakeupPull for PullDown {
+    const EDGE: Edge = Edge::Rising;
+}
+
+/// The EXTI line of a pin is already claimed by the same pin number of another port
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct ExtiConflict {
+    /// The EXTI line, which is the pin number
+    pub line: u8,
+}
+
+/// EXTI lines that were made an interrupt source, one bit per line
+static EXTI_CLAIMED: AtomicU16 = AtomicU16::new(0);
+
+/// Returns the port currently selected for `line` in the SYSCFG mux
+fn exti_source_port(syscfg: &SysCfg, line: u8) -> u32 {
+    let exticr = match line {
+        0..=3 => syscfg.exticr1.read().bits(),
+        4..=7 => syscfg.exticr2.read().bits(),
+        8..=11 => syscfg.exticr3.read().bits(),
+        12..=15 => syscfg.exticr4.read().bits(),
+        _ => unreachable!(),
+    };
+    (exticr >> (4 * (line % 4))) & 0xf
+}
 
 /// External Interrupt Pin
-pub trait ExtiPin {
+///
+/// Implemented for input and output pins, typed or erased, and re-exported by the prelude.
+/// The line of a pin is its pin number, and the port is selected for it in the `EXTICR`
+/// registers of SYSCFG:
+///
+/// ```ignore
+/// let mut syscfg = dp.SYSCFG.constrain();
+/// let mut button = gpioa.pa0.into_pull_up_input();
+/// button.make_interrupt_source(&mut syscfg);
+/// button.trigger_on_edge(&mut dp.EXTI, Edge::Falling);
+/// button.enable_interrupt(&mut dp.EXTI);
+///
+/// // in the EXTI0 interrupt handler
+/// if button.check_interrupt() {
+///     button.clear_interrupt_pending_bit();
+/// }
+/// ```
+///
+/// The trait is sealed, it is implemented for all pins and can't be implemented outside of
+/// this crate.
+pub trait ExtiPin: sealed::Sealed {
     fn make_interrupt_source(&mut self, syscfg: &mut SysCfg);
+    fn try_make_interrupt_source(&mut self, syscfg: &mut SysCfg) -> Result<(), ExtiConflict>;
+    fn release_interrupt_source(&mut self, syscfg: &mut SysCfg);
     fn trigger_on_edge(&mut self, exti: &mut EXTI, level: Edge);
     fn enable_interrupt(&mut self, exti: &mut EXTI);
     fn disable_interrupt(&mut self, exti: &mut EXTI);
@@ -173,11 +256,15 @@ where
     PIN::Mode: Interruptable,
 {
     /// Make corresponding EXTI line sensitive to this pin
+    ///
+    /// This takes the line over from a pin of another port that used it before, see
+    /// [`try_make_interrupt_source`](Self::try_make_interrupt_source) to detect that.
     #[inline(always)]
     fn make_interrupt_source(&mut self, syscfg: &mut SysCfg) {
         let i = self.pin_id();
         let port = self.port_id() as u32;
         let offset = 4 * (i % 4);
+        EXTI_CLAIMED.fetch_or(1 << i, Ordering::Relaxed);
         match i {
             0..=3 => {
                 syscfg.exticr1.modify(|r, w| unsafe {
@@ -203,6 +290,35 @@ where
         }
     }
 
+    /// Make corresponding EXTI line sensitive to this pin, unless it is claimed by another port
+    ///
+    /// EXTI lines are shared by the pins of the same number of all ports, so e.g. PA0 and PB0
+    /// can't both be interrupt sources. Claiming the line again for the same pin succeeds.
+    fn try_make_interrupt_source(&mut self, syscfg: &mut SysCfg) -> Result<(), ExtiConflict> {
+        let i = self.pin_id();
+        let claimed = EXTI_CLAIMED.fetch_or(1 << i, Ordering::Relaxed) & (1 << i) != 0;
+        if claimed && exti_source_port(syscfg, i) != self.port_id() as u32 {
+            return Err(ExtiConflict { line: i });
+        }
+        self.make_interrupt_source(syscfg);
+        Ok(())
+    }
+
+    /// Frees the EXTI line of this pin for other ports, if this pin is its source
+    ///
+    /// The interrupt of the line is disabled as well.
+    fn release_interrupt_source(&mut self, syscfg: &mut SysCfg) {
+        let i = self.pin_id();
+        if exti_source_port(syscfg, i) == self.port_id() as u32 {
+            unsafe {
+                (*EXTI::ptr())
+                    .imr
+                    .modify(|r, w| w.bits(r.bits() & !(1 << i)))
+            };
+            EXTI_CLAIMED.fetch_and(!(1 << i), Ordering::Relaxed);
+        }
+    }
+
     /// Generate interrupt on rising edge, falling edge or both
     #[inline(always)]
     fn trigger_on_edge(&mut self, exti: &mut EXTI, edge: Edge) {
@@ -283,6 +399,17 @@ impl<MODE, const P: char, const N: u8> PinExt for Pin<MODE, P, N> {
     }
 }
 
+impl<MODE> Pin<MODE, 'A', 0> {
+    /// Enables the WKUP function of PA0, which leaves Standby mode on a rising edge
+    ///
+    /// Unlike in Stop mode, GPIO configuration is lost in Standby, so the pin has to be pulled
+    /// low externally to avoid a false wakeup.
+    pub fn enable_standby_wakeup(&mut self, pwr: &mut PWR, enable: bool) {
+        // NOTE(unsafe) EWUP (EWUP1 on some parts) is bit 8 of PWR_CSR
+        unsafe { crate::bb::write(&pwr.csr, 8, enable) }
+    }
+}
+
 impl<MODE, const P: char, const N: u8> Pin<Output<MODE>, P, N> {
     /// Set pin speed
     pub fn set_speed(self, speed: Speed) -> Self {
@@ -296,6 +423,32 @@ impl<MODE, const P: char, const N: u8> Pin<Output<MODE>, P, N> {
 
         self
     }
+
+    /// Returns the output driver type currently configured in OTYPER
+    pub fn output_type(&self) -> OutputType {
+        // NOTE(unsafe) atomic read with no side effects
+        if unsafe { (*Gpio::<P>::ptr()).otyper.read().bits() } & (1 << N) == 0 {
+            OutputType::PushPull
+        } else {
+            OutputType::OpenDrain
+        }
+    }
+
+    /// Changes the output driver type in place
+    ///
+    /// This does not change the type of the pin, so `MODE` no longer reflects the hardware
+    /// until the type is set back. Use `into_push_pull_output` or `into_open_drain_output` for
+    /// a permanent change.
+    pub fn set_output_type(&mut self, otype: OutputType) {
+        // NOTE(unsafe) atomic write of this pin's bit
+        unsafe {
+            crate::bb::write(
+                &(*Gpio::<P>::ptr()).otyper,
+                N,
+                otype == OutputType::OpenDrain,
+            )
+        }
+    }
 }
 
 impl<const P: char, const N: u8> Pin<Output<OpenDrain>, P, N> {
@@ -694,6 +847,35 @@ macro_rules! gpio {
                 }
             }
 
+            impl Parts {
+                /// Puts every pin of the port in analog mode, see [`set_unused_pins_analog`]
+                pub fn all_analog(self) {
+                    super::Gpio::<$port_id>::set_analog(0xffff);
+                }
+
+                /// Configures every pin of the port as push-pull output driven low, to be
+                /// written as one word
+                ///
+                /// On port A this includes the debug pins `PA13` and `PA14`, on port B the
+                /// `PB3` and `PB4` pins of JTAG.
+                pub fn into_whole_port(self) -> super::WholePort<$port_id> {
+                    super::WholePort::configure()
+                }
+            }
+
+            /// Puts the pins of the port selected by `mask` in analog mode without pull resistor
+            ///
+            /// Analog pins have the lowest leakage current in sleep modes. They are disconnected
+            /// from all peripherals though, including the debug port on `PA13` and `PA14`, so call
+            /// this once the pins used by the application are configured.
+            ///
+            /// # Safety
+            ///
+            /// None of the pins in `mask` may be in use, as their type no longer matches their mode.
+            pub unsafe fn set_unused_pins_analog(mask: u16) {
+                super::Gpio::<$port_id>::set_analog(mask);
+            }
+
             pub type $PXn<MODE> = super::PEPin<MODE, $port_id>;
 
             $(
@@ -944,4 +1126,14 @@ impl<const P: char> Gpio<P> {
             _ => crate::pac::GPIOA::ptr(),
         }
     }
+
+    fn set_analog(mask: u16) {
+        let bits = (0..16)
+            .filter(|i| mask & (1 << i) != 0)
+            .fold(0, |bits, i| bits | 0b11 << (2 * i));
+        unsafe {
+            (*Self::ptr()).pupdr.modify(|r, w| w.bits(r.bits() & !bits));
+            (*Self::ptr()).moder.modify(|r, w| w.bits(r.bits() | bits));
+        }
+    }
 }
@@ -0,0 +1,236 @@
+//! Declarative pin setup for board support files
+//!
+//! Instead of chaining `into_*` calls, all pins of a board can be described in one table, which
+//! is easier to audit against the schematic:
+//!
+//! ```ignore
+//! const PINS: &[PinConfig] = &[
+//!     PinConfig::new('A', 5, PinConfigMode::Output).speed(Speed::High),
+//!     PinConfig::new('B', 6, PinConfigMode::AlternateOpenDrain).af(4).pull(Pull::Up),
+//!     PinConfig::new('B', 7, PinConfigMode::AlternateOpenDrain).af(4).pull(Pull::Up),
+//! ];
+//!
+//! unsafe { configure_pins(PINS) };
+//! let led: ErasedPin<Output<PushPull>> = unsafe { PINS[0].erase() };
+//! ```
+//!
+//! The table bypasses the type states of the split ports, so the pins it lists must not be
+//! used through their `Parts` fields.
+use super::{ErasedPin, Speed};
+
+/// Pull resistor of a pin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pull {
+    None = 0b00,
+    Up = 0b01,
+    Down = 0b10,
+}
+
+/// Mode of a pin in a [`PinConfig`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PinConfigMode {
+    Input,
+    Output,
+    OutputOpenDrain,
+    Alternate,
+    AlternateOpenDrain,
+    Analog,
+}
+
+/// Configuration of one pin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinConfig {
+    /// Port letter, `'A'` to `'K'`, of a port the chip has
+    pub port: char,
+    /// Pin number within the port, below 16
+    pub pin: u8,
+    pub mode: PinConfigMode,
+    pub pull: Pull,
+    pub speed: Speed,
+    /// Alternate function number, only used in the alternate modes
+    pub af: u8,
+}
+
+impl PinConfig {
+    /// Configuration of pin `pin` of port `port` in `mode`, without pull resistor, at low
+    /// speed and with alternate function 0
+    pub const fn new(port: char, pin: u8, mode: PinConfigMode) -> Self {
+        Self {
+            port,
+            pin,
+            mode,
+            pull: Pull::None,
+            speed: Speed::Low,
+            af: 0,
+        }
+    }
+
+    pub const fn pull(mut self, pull: Pull) -> Self {
+        self.pull = pull;
+        self
+    }
+
+    pub const fn speed(mut self, speed: Speed) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub const fn af(mut self, af: u8) -> Self {
+        self.af = af;
+        self
+    }
+
+    /// Returns the configured pin as erased pin
+    ///
+    /// # Safety
+    ///
+    /// `MODE` has to match the configured mode, and the pin must not be used otherwise.
+    pub unsafe fn erase<MODE>(&self) -> ErasedPin<MODE> {
+        ErasedPin::new(self.port_index(), self.pin)
+    }
+
+    /// Reads the current configuration of pin `pin` of port `port` back from the registers
+    ///
+    /// Applying the result restores the pin after it was temporarily reconfigured.
+    pub(crate) fn read(port: char, pin: u8) -> Self {
+        let block = Self::new(port, pin, PinConfigMode::Input).block();
+        let n = u32::from(pin);
+        let field2 = |bits: u32| (bits >> (2 * n)) & 0b11;
+
+        let open_drain = block.otyper.read().bits() & (1 << n) != 0;
+        let mode = match (field2(block.moder.read().bits()), open_drain) {
+            (0b00, _) => PinConfigMode::Input,
+            (0b01, false) => PinConfigMode::Output,
+            (0b01, true) => PinConfigMode::OutputOpenDrain,
+            (0b10, false) => PinConfigMode::Alternate,
+            (0b10, true) => PinConfigMode::AlternateOpenDrain,
+            _ => PinConfigMode::Analog,
+        };
+        let pull = match field2(block.pupdr.read().bits()) {
+            0b01 => Pull::Up,
+            0b10 => Pull::Down,
+            _ => Pull::None,
+        };
+        let speed = match field2(block.ospeedr.read().bits()) {
+            0b00 => Speed::Low,
+            0b01 => Speed::Medium,
+            0b10 => Speed::High,
+            _ => Speed::VeryHigh,
+        };
+        let afr = if n < 8 {
+            block.afrl.read().bits()
+        } else {
+            block.afrh.read().bits()
+        };
+        let af = ((afr >> (4 * (n % 8))) & 0b1111) as u8;
+
+        Self::new(port, pin, mode).pull(pull).speed(speed).af(af)
+    }
+
+    /// Index of the port, after checking that the entry names a pin of the chip
+    ///
+    /// Every register access goes through this, a wrong entry would otherwise address memory
+    /// past the GPIO register blocks.
+    fn port_index(&self) -> u8 {
+        assert!(
+            port_present(self.port) && self.pin < 16,
+            "no pin P{}{} on this chip",
+            self.port,
+            self.pin
+        );
+        self.port as u8 - b'A'
+    }
+
+    fn block(&self) -> &'static crate::pac::gpioa::RegisterBlock {
+        // all GPIO register blocks share one layout and are 0x400 bytes apart
+        const GPIO_REGISTER_OFFSET: usize = 0x0400;
+        let offset = GPIO_REGISTER_OFFSET * self.port_index() as usize;
+        unsafe {
+            &*((crate::pac::GPIOA::ptr() as usize + offset)
+                as *const crate::pac::gpioa::RegisterBlock)
+        }
+    }
+
+    fn apply(&self) {
+        let block = self.block();
+
+        let (moder, open_drain) = match self.mode {
+            PinConfigMode::Input => (0b00, false),
+            PinConfigMode::Output => (0b01, false),
+            PinConfigMode::OutputOpenDrain => (0b01, true),
+            PinConfigMode::Alternate => (0b10, false),
+            PinConfigMode::AlternateOpenDrain => (0b10, true),
+            PinConfigMode::Analog => (0b11, false),
+        };
+        let n = u32::from(self.pin);
+        let offset2 = 2 * n;
+
+        unsafe {
+            block.pupdr.modify(|r, w| {
+                w.bits((r.bits() & !(0b11 << offset2)) | ((self.pull as u32) << offset2))
+            });
+            block
+                .otyper
+                .modify(|r, w| w.bits((r.bits() & !(0b1 << n)) | (u32::from(open_drain) << n)));
+            block.ospeedr.modify(|r, w| {
+                w.bits((r.bits() & !(0b11 << offset2)) | ((self.speed as u32) << offset2))
+            });
+            if moder == 0b10 {
+                let offset4 = 4 * (n % 8);
+                let af = u32::from(self.af & 0xf);
+                if n < 8 {
+                    block
+                        .afrl
+                        .modify(|r, w| w.bits((r.bits() & !(0b1111 << offset4)) | (af << offset4)));
+                } else {
+                    block
+                        .afrh
+                        .modify(|r, w| w.bits((r.bits() & !(0b1111 << offset4)) | (af << offset4)));
+                }
+            }
+            block
+                .moder
+                .modify(|r, w| w.bits((r.bits() & !(0b11 << offset2)) | (moder << offset2)));
+        }
+    }
+}
+
+/// Returns true if the chip has GPIO port `port`
+fn port_present(port: char) -> bool {
+    match port {
+        'A' | 'B' | 'C' | 'H' => true,
+        'D' => cfg!(feature = "gpiod"),
+        'E' => cfg!(feature = "gpioe"),
+        'F' => cfg!(feature = "gpiof"),
+        'G' => cfg!(feature = "gpiog"),
+        'I' => cfg!(feature = "gpioi"),
+        'J' => cfg!(feature = "gpioj"),
+        'K' => cfg!(feature = "gpiok"),
+        _ => false,
+    }
+}
+
+/// Applies every configuration of `table`, in order
+///
+/// Nothing is returned: an [`ErasedPin`] carries its mode in its type, which differs between
+/// the entries of a table, so each pin is taken out with [`PinConfig::erase`] and the mode it
+/// was configured to.
+///
+/// # Panics
+///
+/// If an entry names a port the chip doesn't have, or a pin number above 15. The table is
+/// checked before any pin is configured.
+///
+/// # Safety
+///
+/// The pins of `table` must not be in use through their typed `Pin` values, as their type no
+/// longer matches their mode. Ports that the table refers to must have been split, to enable
+/// their clock.
+pub unsafe fn configure_pins(table: &[PinConfig]) {
+    for config in table {
+        config.port_index();
+    }
+    for config in table {
+        config.apply();
+    }
+}
@@ -393,6 +393,30 @@ impl<MODE, const P: char, const N: u8> Pin<MODE, P, N> {
         Pin::new()
     }
 
+    /// Configures the pin as a pulled input that wakes the MCU from Stop mode
+    ///
+    /// The GPIO registers, including the pull resistor, are retained in Stop mode, so the line
+    /// stays at a defined level instead of floating and causing a false wakeup. The EXTI line of
+    /// the pin is set up to trigger on the edge leaving that level: falling with `PullUp`,
+    /// rising with `PullDown`. The EXTI interrupt still has to be unmasked in the NVIC.
+    pub fn configure_for_stop<PULL>(
+        mut self,
+        syscfg: &mut SysCfg,
+        exti: &mut EXTI,
+    ) -> Pin<Input<PULL>, P, N>
+    where
+        PULL: WakeupPull,
+        Input<PULL>: PinMode,
+    {
+        self.mode::<Input<PULL>>();
+        let mut pin = Pin::new();
+        pin.make_interrupt_source(syscfg);
+        pin.trigger_on_edge(exti, PULL::EDGE);
+        pin.clear_interrupt_pending_bit();
+        pin.enable_interrupt(exti);
+        pin
+    }
+
     /// Configures the pin to operate as an open drain output pin
     /// Initial state will be low.
     pub fn into_open_drain_output(mut self) -> Pin<Output<OpenDrain>, P, N> {
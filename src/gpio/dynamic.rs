@@ -0,0 +1,212 @@
+use super::*;
+
+/// Pin type with dynamic mode
+///
+/// - `P` is port name: `A` for GPIOA, `B` for GPIOB, etc.
+/// - `N` is pin number: from `0` to `15`.
+///
+/// The mode can be changed at runtime with the `make_*` methods, for protocols that switch a
+/// line between input and output. Reading or writing in the wrong mode returns
+/// `PinModeError::IncorrectMode`.
+pub struct DynamicPin<const P: char, const N: u8> {
+    /// Current pin mode
+    mode: Dynamic,
+}
+
+/// Tracks the current pin state for dynamic pins
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dynamic {
+    InputFloating,
+    InputPullUp,
+    InputPullDown,
+    OutputPushPull,
+    OutputOpenDrain,
+}
+
+/// Error for [`DynamicPin`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PinModeError {
+    /// The pin is not in the mode required by the operation
+    IncorrectMode,
+}
+
+impl Dynamic {
+    /// Returns `true` if the pin can be read in this mode
+    ///
+    /// Open-drain outputs read back the level of the line.
+    pub fn is_input(&self) -> bool {
+        use Dynamic::*;
+        match self {
+            InputFloating | InputPullUp | InputPullDown | OutputOpenDrain => true,
+            OutputPushPull => false,
+        }
+    }
+
+    /// Returns `true` if the pin can be driven in this mode
+    pub fn is_output(&self) -> bool {
+        use Dynamic::*;
+        match self {
+            InputFloating | InputPullUp | InputPullDown => false,
+            OutputPushPull | OutputOpenDrain => true,
+        }
+    }
+}
+
+impl<MODE, const P: char, const N: u8> Pin<MODE, P, N> {
+    /// Configures the pin as floating input and erases its mode from the type
+    pub fn into_dynamic(self) -> DynamicPin<P, N> {
+        self.into_floating_input();
+        DynamicPin::new(Dynamic::InputFloating)
+    }
+}
+
+impl<const P: char, const N: u8> DynamicPin<P, N> {
+    const fn new(mode: Dynamic) -> Self {
+        Self { mode }
+    }
+
+    /// Typed pin in the state all mode changes start from
+    ///
+    /// The register writes of the `into_*` methods don't depend on the type state of the pin,
+    /// but starting every transition from the same state keeps them correct should they ever
+    /// do.
+    #[inline(always)]
+    fn pin() -> Pin<Input<Floating>, P, N> {
+        Pin::new()
+    }
+
+    /// Returns the mode the pin is currently configured in
+    #[inline(always)]
+    pub fn mode(&self) -> Dynamic {
+        self.mode
+    }
+
+    #[inline]
+    pub fn make_pull_up_input(&mut self) {
+        Self::pin().into_pull_up_input();
+        self.mode = Dynamic::InputPullUp;
+    }
+
+    #[inline]
+    pub fn make_pull_down_input(&mut self) {
+        Self::pin().into_pull_down_input();
+        self.mode = Dynamic::InputPullDown;
+    }
+
+    #[inline]
+    pub fn make_floating_input(&mut self) {
+        Self::pin().into_floating_input();
+        self.mode = Dynamic::InputFloating;
+    }
+
+    #[inline]
+    pub fn make_push_pull_output(&mut self) {
+        Self::pin().into_push_pull_output();
+        self.mode = Dynamic::OutputPushPull;
+    }
+
+    #[inline]
+    pub fn make_push_pull_output_in_state(&mut self, state: PinState) {
+        Self::pin().into_push_pull_output_in_state(state);
+        self.mode = Dynamic::OutputPushPull;
+    }
+
+    #[inline]
+    pub fn make_open_drain_output(&mut self) {
+        Self::pin().into_open_drain_output();
+        self.mode = Dynamic::OutputOpenDrain;
+    }
+
+    #[inline]
+    pub fn make_open_drain_output_in_state(&mut self, state: PinState) {
+        Self::pin().into_open_drain_output_in_state(state);
+        self.mode = Dynamic::OutputOpenDrain;
+    }
+}
+
+impl<const P: char, const N: u8> DynamicPin<P, N> {
+    /// Drives the pin to `state`
+    #[inline]
+    pub fn set_state(&mut self, state: PinState) -> Result<(), PinModeError> {
+        if self.mode.is_output() {
+            Self::pin()._set_state(state);
+            Ok(())
+        } else {
+            Err(PinModeError::IncorrectMode)
+        }
+    }
+
+    /// Inverts the driven state of the pin
+    ///
+    /// The new state is written to BSRR, so other pins of the port are not affected even if
+    /// they change at the same time.
+    #[inline]
+    pub fn toggle(&mut self) -> Result<(), PinModeError> {
+        let state = if self.is_set_low()? {
+            PinState::High
+        } else {
+            PinState::Low
+        };
+        self.set_state(state)
+    }
+}
+
+impl<const P: char, const N: u8> OutputPin for DynamicPin<P, N> {
+    type Error = PinModeError;
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        if self.mode.is_output() {
+            Pin::<Output<PushPull>, P, N>::new().set_high();
+            Ok(())
+        } else {
+            Err(PinModeError::IncorrectMode)
+        }
+    }
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        if self.mode.is_output() {
+            Pin::<Output<PushPull>, P, N>::new().set_low();
+            Ok(())
+        } else {
+            Err(PinModeError::IncorrectMode)
+        }
+    }
+}
+
+impl<const P: char, const N: u8> InputPin for DynamicPin<P, N> {
+    type Error = PinModeError;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        self.is_low().map(|b| !b)
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        if self.mode.is_input() {
+            Ok(Pin::<Input<Floating>, P, N>::new().is_low())
+        } else {
+            Err(PinModeError::IncorrectMode)
+        }
+    }
+}
+
+impl<const P: char, const N: u8> StatefulOutputPin for DynamicPin<P, N> {
+    fn is_set_high(&self) -> Result<bool, Self::Error> {
+        self.is_set_low().map(|b| !b)
+    }
+
+    fn is_set_low(&self) -> Result<bool, Self::Error> {
+        if self.mode.is_output() {
+            Ok(Self::pin()._is_set_low())
+        } else {
+            Err(PinModeError::IncorrectMode)
+        }
+    }
+}
+
+impl<const P: char, const N: u8> ToggleableOutputPin for DynamicPin<P, N> {
+    type Error = PinModeError;
+
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        self.toggle()
+    }
+}
@@ -0,0 +1,64 @@
+use super::*;
+
+/// Key matrix scanner
+///
+/// The rows are driven low one at a time while the columns, pulled up, are read back. A key
+/// that connects the active row with a column pulls that column low.
+///
+/// Open-drain rows are recommended: with push-pull rows, pressing two keys of the same column
+/// shorts a high row against the active low one.
+pub struct Matrix<ROWMODE, const ROWS: usize, const COLS: usize> {
+    rows: [ErasedPin<Output<ROWMODE>>; ROWS],
+    cols: [ErasedPin<Input<PullUp>>; COLS],
+    settle_cycles: u32,
+}
+
+impl<ROWMODE, const ROWS: usize, const COLS: usize> Matrix<ROWMODE, ROWS, COLS> {
+    /// Creates the scanner and releases (drives high) all rows
+    pub fn new(
+        mut rows: [ErasedPin<Output<ROWMODE>>; ROWS],
+        cols: [ErasedPin<Input<PullUp>>; COLS],
+    ) -> Self {
+        for row in rows.iter_mut() {
+            row.set_high();
+        }
+        Self {
+            rows,
+            cols,
+            settle_cycles: 100,
+        }
+    }
+
+    /// Sets how many core clock cycles to wait after selecting a row before the columns are read
+    ///
+    /// The default of 100 cycles is enough for short traces; long cables or weak pull-ups need
+    /// more time for the columns to settle.
+    pub fn with_settle_cycles(mut self, cycles: u32) -> Self {
+        self.settle_cycles = cycles;
+        self
+    }
+
+    /// Scans all rows, returning `true` for every pressed key as `[row][column]`
+    pub fn scan(&mut self) -> [[bool; COLS]; ROWS] {
+        let mut keys = [[false; COLS]; ROWS];
+        for (row, pressed) in self.rows.iter_mut().zip(keys.iter_mut()) {
+            row.set_low();
+            cortex_m::asm::delay(self.settle_cycles);
+            for (col, key) in self.cols.iter().zip(pressed.iter_mut()) {
+                *key = col.is_low();
+            }
+            row.set_high();
+        }
+        keys
+    }
+
+    /// Releases the row and column pins
+    pub fn release(
+        self,
+    ) -> (
+        [ErasedPin<Output<ROWMODE>>; ROWS],
+        [ErasedPin<Input<PullUp>>; COLS],
+    ) {
+        (self.rows, self.cols)
+    }
+}
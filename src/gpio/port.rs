@@ -0,0 +1,177 @@
+use super::*;
+
+macro_rules! out_port {
+    ($(#[$meta:meta])* $name:ident: $($i:literal => $N:ident,)+) => {
+        $(#[$meta])*
+        pub struct $name<MODE, const P: char, $(const $N: u8,)+> {
+            pins: ($(Pin<Output<MODE>, P, $N>,)+),
+        }
+
+        impl<MODE, const P: char, $(const $N: u8,)+> $name<MODE, P, $($N,)+> {
+            /// Groups `pins` into a port, the first pin is bit 0 of the words
+            pub const fn new(pins: ($(Pin<Output<MODE>, P, $N>,)+)) -> Self {
+                Self { pins }
+            }
+
+            /// Releases the pins
+            pub fn release(self) -> ($(Pin<Output<MODE>, P, $N>,)+) {
+                self.pins
+            }
+
+            /// Drives all pins at once to the bits of `word`, higher bits are ignored
+            #[inline]
+            pub fn write(&mut self, word: u8) {
+                let mut bsrr = 0u32;
+                $(
+                    bsrr |= if word & (1 << $i) != 0 {
+                        1 << $N
+                    } else {
+                        1 << ($N + 16)
+                    };
+                )+
+                // NOTE(unsafe) atomic write to a stateless register
+                unsafe { (*Gpio::<P>::ptr()).bsrr.write(|w| w.bits(bsrr)) }
+            }
+
+            /// Returns the word the pins are driven to, read from ODR
+            #[inline]
+            pub fn read_back(&self) -> u8 {
+                // NOTE(unsafe) atomic read with no side effects
+                let odr = unsafe { (*Gpio::<P>::ptr()).odr.read().bits() };
+                0 $(| (((odr >> $N) & 1) as u8) << $i)+
+            }
+        }
+    };
+}
+
+macro_rules! in_port {
+    ($(#[$meta:meta])* $name:ident: $($i:literal => $N:ident,)+) => {
+        $(#[$meta])*
+        pub struct $name<MODE, const P: char, $(const $N: u8,)+> {
+            pins: ($(Pin<Input<MODE>, P, $N>,)+),
+        }
+
+        impl<MODE, const P: char, $(const $N: u8,)+> $name<MODE, P, $($N,)+> {
+            /// Groups `pins` into a port, the first pin is bit 0 of the words
+            pub const fn new(pins: ($(Pin<Input<MODE>, P, $N>,)+)) -> Self {
+                Self { pins }
+            }
+
+            /// Releases the pins
+            pub fn release(self) -> ($(Pin<Input<MODE>, P, $N>,)+) {
+                self.pins
+            }
+
+            /// Samples all pins at once, from a single read of IDR
+            #[inline]
+            pub fn read_u8(&self) -> u8 {
+                // NOTE(unsafe) atomic read with no side effects
+                let idr = unsafe { (*Gpio::<P>::ptr()).idr.read().bits() };
+                0 $(| (((idr >> $N) & 1) as u8) << $i)+
+            }
+        }
+    };
+}
+
+out_port!(
+    /// Four output pins of one port, written at once
+    ///
+    /// All pins change in the same cycle with a single BSRR write, without glitches on a
+    /// parallel bus. The pins have to be on the same port; other pins of the port are not
+    /// affected.
+    FourBitOutputPort:
+    0 => N0,
+    1 => N1,
+    2 => N2,
+    3 => N3,
+);
+
+out_port!(
+    /// Eight output pins of one port, written at once
+    ///
+    /// See [`FourBitOutputPort`], this is the width of the data bus of a parallel LCD.
+    EightBitOutputPort:
+    0 => N0,
+    1 => N1,
+    2 => N2,
+    3 => N3,
+    4 => N4,
+    5 => N5,
+    6 => N6,
+    7 => N7,
+);
+
+in_port!(
+    /// Four input pins of one port, read at once
+    ///
+    /// All pins are sampled in the same cycle, so a BCD switch or a parallel bus that changes
+    /// while it is read gives either the old or the new value of each bit, never a mix of reads
+    /// from different times.
+    FourBitInputPort:
+    0 => N0,
+    1 => N1,
+    2 => N2,
+    3 => N3,
+);
+
+/// All 16 pins of a port as one push-pull output word
+///
+/// `write` updates the whole port with a single ODR write, `write_masked` changes only the
+/// selected pins with a BSRR write, which doesn't race with other code driving the rest of
+/// the port.
+pub struct WholePort<const P: char> {
+    _private: (),
+}
+
+impl<const P: char> WholePort<P> {
+    /// Takes over the port, whose pins are already configured as push-pull outputs
+    ///
+    /// # Safety
+    ///
+    /// All 16 pins of the port must be configured as push-pull outputs first, and must not be
+    /// used otherwise. Prefer the `into_whole_port` method of the port `Parts`, which does the
+    /// configuration.
+    pub const unsafe fn new() -> Self {
+        Self { _private: () }
+    }
+
+    /// Configures all pins as push-pull outputs driven low
+    pub(super) fn configure() -> Self {
+        // NOTE(unsafe) the caller owns all pins of the port
+        unsafe {
+            let gpio = &*Gpio::<P>::ptr();
+            gpio.odr.write(|w| w.bits(0));
+            gpio.otyper.write(|w| w.bits(0));
+            gpio.pupdr.write(|w| w.bits(0));
+            gpio.moder.write(|w| w.bits(0x5555_5555));
+        }
+        Self { _private: () }
+    }
+
+    /// Drives all pins to the bits of `value`
+    #[inline]
+    pub fn write(&mut self, value: u16) {
+        // NOTE(unsafe) the port is owned
+        unsafe { (*Gpio::<P>::ptr()).odr.write(|w| w.bits(u32::from(value))) }
+    }
+
+    /// Drives the pins selected by `mask` to the bits of `value`, leaving the others
+    #[inline]
+    pub fn write_masked(&mut self, value: u16, mask: u16) {
+        let set = value & mask;
+        let reset = !value & mask;
+        // NOTE(unsafe) atomic write to a stateless register
+        unsafe {
+            (*Gpio::<P>::ptr())
+                .bsrr
+                .write(|w| w.bits(u32::from(reset) << 16 | u32::from(set)))
+        }
+    }
+
+    /// Returns the word the pins are driven to, read from ODR
+    #[inline]
+    pub fn read_back(&self) -> u16 {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Gpio::<P>::ptr()).odr.read().bits() as u16 }
+    }
+}
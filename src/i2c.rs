@@ -1,10 +1,15 @@
 use core::ops::Deref;
+use cortex_m::peripheral::DWT;
+use embedded_hal::blocking::delay::DelayUs;
 use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
 
 use crate::pac::i2c1;
 use crate::rcc::{Enable, Reset};
 
-use crate::gpio::{Const, OpenDrain, PinA, SetAlternate};
+use crate::gpio::{
+    configure_pins, Const, ErasedPin, OpenDrain, Output, PinA, PinConfig, PinConfigMode, PinExt,
+    SetAlternate,
+};
 #[cfg(feature = "i2c3")]
 use crate::pac::I2C3;
 use crate::pac::{I2C1, I2C2, RCC};
@@ -16,15 +21,18 @@ use crate::gpio::gpiof;
 use crate::gpio::{gpioa, gpiob, gpioc, gpioh};
 
 use crate::rcc::Clocks;
-use crate::time::{Hertz, U32Ext};
+use crate::time::{Hertz, MilliSeconds, U32Ext};
 
-#[derive(Debug, Eq, PartialEq)]
+pub mod dma;
+pub mod eeprom;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum DutyCycle {
     Ratio2to1,
     Ratio16to9,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Mode {
     Standard {
         frequency: Hertz,
@@ -74,10 +82,47 @@ where
     }
 }
 
+#[cfg(not(any(
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f415",
+    feature = "stm32f417"
+)))]
+/// Noise filters on the SDA and SCL inputs (`FLTR`)
+///
+/// Available on all parts except the STM32F405/407/415/417, which have no `FLTR` register.
+/// The reset state has the analog filter on and the digital filter off.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NoiseFilter {
+    /// Analog filter, suppressing spikes shorter than 50 ns
+    pub analog: bool,
+    /// Digital filter, suppressing spikes up to this many PCLK1 periods, from 1 to 15; 0 turns
+    /// it off
+    pub digital: u8,
+}
+
+#[cfg(not(any(
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f415",
+    feature = "stm32f417"
+)))]
+impl Default for NoiseFilter {
+    fn default() -> Self {
+        Self {
+            analog: true,
+            digital: 0,
+        }
+    }
+}
+
 /// I2C abstraction
 pub struct I2c<I2C: Instance, PINS> {
     i2c: I2C,
     pins: PINS,
+    mode: Mode,
+    retries: u8,
+    stretch_timeout: u32,
 }
 
 pub struct Scl;
@@ -88,12 +133,14 @@ impl crate::Sealed for Sda {}
 pub trait Pins<I2C> {
     fn set_alt_mode(&mut self);
     fn restore_mode(&mut self);
+    /// Port and pin numbers of SCL and SDA, used to drive them as GPIO during bus recovery
+    fn ids(&self) -> ((u8, u8), (u8, u8));
 }
 
 impl<I2C, SCL, SDA, const SCLA: u8, const SDAA: u8> Pins<I2C> for (SCL, SDA)
 where
-    SCL: PinA<Scl, I2C, A = Const<SCLA>> + SetAlternate<OpenDrain, SCLA>,
-    SDA: PinA<Sda, I2C, A = Const<SDAA>> + SetAlternate<OpenDrain, SDAA>,
+    SCL: PinA<Scl, I2C, A = Const<SCLA>> + SetAlternate<OpenDrain, SCLA> + PinExt,
+    SDA: PinA<Sda, I2C, A = Const<SDAA>> + SetAlternate<OpenDrain, SDAA> + PinExt,
 {
     fn set_alt_mode(&mut self) {
         self.0.set_alt_mode();
@@ -103,27 +150,133 @@ where
         self.0.restore_mode();
         self.1.restore_mode();
     }
+    fn ids(&self) -> ((u8, u8), (u8, u8)) {
+        (
+            (self.0.port_id(), self.0.pin_id()),
+            (self.1.port_id(), self.1.pin_id()),
+        )
+    }
 }
 
+/// I2C error
+///
+/// The variants map to the error flags of `SR1`, which are cleared before the error is
+/// returned, so the next transfer starts without stale flags.
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum Error {
+    /// Overrun or underrun (`OVR`)
     OVERRUN,
+    /// The address or a data byte was not acknowledged (`AF`)
     NACK,
+    /// SMBus timeout (`TIMEOUT`), clock stretch timeout, or the bus got stuck before or after
+    /// a transfer
     TIMEOUT,
-    // Note: The BUS error type is not currently returned, but is maintained for backwards
-    // compatibility.
+    /// Misplaced START or STOP condition (`BERR`)
+    ///
+    /// Not returned: the errata of the F4 reports that the flag can be set spuriously, so it is
+    /// cleared and ignored. Kept for backwards compatibility.
     BUS,
+    /// Packet error checking byte mismatch (`PECERR`), see [`I2c::write_read_pec`]
     CRC,
+    /// Another master won the arbitration (`ARLO`), the peripheral switched to slave mode
     ARBITRATION,
+    /// The bus did not become idle in time, see [`I2c::wait_bus_idle`]
+    BUSY,
 }
 
-pub trait Instance: crate::Sealed + Deref<Target = i2c1::RegisterBlock> + Enable + Reset {}
+/// 7-bit slave address, without the R/W bit
+///
+/// Converting from an `u8` of `0x80` or above (usually an address that was already shifted
+/// left by one) panics in debug builds.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct SevenBitAddress(u8);
 
-impl Instance for I2C1 {}
-impl Instance for I2C2 {}
+impl SevenBitAddress {
+    /// Returns `None` if `addr` does not fit into 7 bits
+    pub const fn new(addr: u8) -> Option<Self> {
+        if addr < 0x80 {
+            Some(Self(addr))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the raw 7-bit address
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl From<u8> for SevenBitAddress {
+    fn from(addr: u8) -> Self {
+        debug_assert!(
+            addr < 0x80,
+            "I2C address {:#04x} does not fit into 7 bits, pass it unshifted",
+            addr
+        );
+        Self(addr)
+    }
+}
+
+/// Slave address, in 7-bit or 10-bit format
+///
+/// 10-bit addresses are sent as a two-byte header, `0b11110xx0` with the two high address
+/// bits followed by the low eight bits. Only the addressing of the master changes; the `ADD10`
+/// bit of `OAR1` selects the own address format in slave mode and is not involved.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Address {
+    SevenBit(u8),
+    TenBit(u16),
+}
+
+impl From<SevenBitAddress> for Address {
+    fn from(addr: SevenBitAddress) -> Self {
+        Self::SevenBit(addr.get())
+    }
+}
+
+impl From<u8> for Address {
+    fn from(addr: u8) -> Self {
+        SevenBitAddress::from(addr).into()
+    }
+}
+
+/// Delay before the first retry of a failed transaction, in microseconds
+const RETRY_BACKOFF_US: u32 = 100;
+
+/// Number of polls of the START, address and STOP phases before giving up with
+/// `Error::TIMEOUT`, a few milliseconds at the highest core clock
+///
+/// These phases only wait for the peripheral itself, the data bytes are bounded by the
+/// [stretch timeout](I2c::with_stretch_timeout) instead.
+const POLL_LIMIT: u32 = 100_000;
+
+pub trait Instance: crate::Sealed + Deref<Target = i2c1::RegisterBlock> + Enable + Reset {
+    #[doc(hidden)]
+    fn ptr() -> *const i2c1::RegisterBlock;
+}
+
+macro_rules! hal {
+    ($($I2C:ty,)+) => {
+        $(
+            impl Instance for $I2C {
+                fn ptr() -> *const i2c1::RegisterBlock {
+                    <$I2C>::ptr() as *const _
+                }
+            }
+        )+
+    };
+}
+
+hal! {
+    I2C1,
+    I2C2,
+}
 
 #[cfg(feature = "i2c3")]
-impl Instance for I2C3 {}
+hal! {
+    I2C3,
+}
 
 impl<I2C, PINS> I2c<I2C, PINS>
 where
@@ -142,8 +295,14 @@ where
 
         pins.set_alt_mode();
 
-        let i2c = I2c { i2c, pins };
-        i2c.i2c_init(mode, clocks.pclk1());
+        let i2c = I2c {
+            i2c,
+            pins,
+            mode: mode.into(),
+            retries: 0,
+            stretch_timeout: 0,
+        };
+        i2c.i2c_init(i2c.mode, clocks.pclk1());
         i2c
     }
 
@@ -152,6 +311,71 @@ where
 
         (self.i2c, self.pins)
     }
+
+    /// Frees a bus that a slave holds by keeping SDA low, then re-initializes the peripheral
+    ///
+    /// This happens when the master was reset in the middle of a read: the slave still waits
+    /// for clock pulses to finish its byte. SCL is driven as GPIO, with up to 9 clock pulses
+    /// until the slave releases SDA, followed by a START and a STOP condition. The pins get
+    /// their previous configuration back, pull resistors included, and the peripheral is reset
+    /// keeping its timing, PEC and clock stretching settings. Fails with `Error::BUSY` if SDA
+    /// is still low after that.
+    pub fn release_and_recover<D: DelayUs<u32>>(&mut self, delay: &mut D) -> Result<(), Error> {
+        // half a clock period at 100 kHz
+        const HALF_PERIOD_US: u32 = 5;
+
+        self.i2c.cr1.modify(|_, w| w.pe().clear_bit());
+
+        let ((scl_port, scl_pin), (sda_port, sda_pin)) = self.pins.ids();
+        let saved = [
+            PinConfig::read((b'A' + scl_port) as char, scl_pin),
+            PinConfig::read((b'A' + sda_port) as char, sda_pin),
+        ];
+        // keep the pull resistors, which may be the only pull-ups of the bus
+        let config = |saved: &PinConfig| {
+            PinConfig::new(saved.port, saved.pin, PinConfigMode::OutputOpenDrain).pull(saved.pull)
+        };
+        let lines = [config(&saved[0]), config(&saved[1])];
+        // NOTE(unsafe) the pins are owned, and get their saved configuration back below
+        let mut scl: ErasedPin<Output<OpenDrain>> = unsafe { lines[0].erase() };
+        let mut sda: ErasedPin<Output<OpenDrain>> = unsafe { lines[1].erase() };
+        // release both lines before switching them to GPIO
+        scl.set_high();
+        sda.set_high();
+        unsafe { configure_pins(&lines) };
+
+        for _ in 0..9 {
+            if sda.is_high() {
+                break;
+            }
+            scl.set_low();
+            delay.delay_us(HALF_PERIOD_US);
+            scl.set_high();
+            delay.delay_us(HALF_PERIOD_US);
+        }
+        let released = sda.is_high();
+
+        // START followed by STOP, SDA falls and rises while SCL is high, which resets the
+        // slaves to wait for a new transaction
+        sda.set_low();
+        delay.delay_us(HALF_PERIOD_US);
+        scl.set_high();
+        delay.delay_us(HALF_PERIOD_US);
+        sda.set_high();
+        delay.delay_us(HALF_PERIOD_US);
+
+        unsafe { configure_pins(&saved) };
+
+        // clear the state machine, which may still consider the bus busy
+        self.software_reset();
+        self.i2c.cr1.modify(|_, w| w.pe().set_bit());
+
+        if released {
+            Ok(())
+        } else {
+            Err(Error::BUSY)
+        }
+    }
 }
 
 impl<I2C, PINS> I2c<I2C, PINS>
@@ -223,6 +447,493 @@ where
         self.i2c.cr1.modify(|_, w| w.pe().set_bit());
     }
 
+    /// Recomputes the bus timings for new `clocks`, keeping the configured mode and frequency
+    ///
+    /// Call this after the system clock was changed at runtime, between transactions. A
+    /// [stretch timeout](Self::with_stretch_timeout) is counted in core cycles and has to be set
+    /// again.
+    pub fn reconfigure(&mut self, clocks: &Clocks) {
+        self.i2c_init(self.mode, clocks.pclk1());
+    }
+
+    /// Sets how many times the `*_with_retries` methods repeat a transaction
+    /// that failed with a NACK, bus or arbitration error.
+    pub fn with_retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Enables the packet error checking of SMBus (`ENPEC`), see
+    /// [`write_read_pec`](Self::write_read_pec)
+    ///
+    /// The peripheral then computes the CRC-8 of every byte on the bus, addresses included.
+    pub fn with_pec(self, enable: bool) -> Self {
+        self.i2c.cr1.modify(|_, w| w.enpec().bit(enable));
+        self
+    }
+
+    /// Configures the noise filters of the SDA and SCL inputs
+    ///
+    /// The filters suppress spikes that would otherwise show up as misplaced START or STOP
+    /// conditions, reported as bus errors, on buses next to motors or switching supplies. The
+    /// digital filter delays the inputs by up to 15 PCLK1 periods, which slightly lowers the
+    /// SCL frequency in fast mode. See [`NoiseFilter`] for the parts that support this.
+    #[cfg(not(any(
+        feature = "stm32f405",
+        feature = "stm32f407",
+        feature = "stm32f415",
+        feature = "stm32f417"
+    )))]
+    pub fn with_noise_filter(self, filter: NoiseFilter) -> Self {
+        // the filters may only be changed while the peripheral is disabled
+        self.i2c.cr1.modify(|_, w| w.pe().clear_bit());
+        self.i2c.fltr.write(|w| unsafe {
+            w.anoff()
+                .bit(!filter.analog)
+                .dnf()
+                .bits(filter.digital.min(15))
+        });
+        self.i2c.cr1.modify(|_, w| w.pe().set_bit());
+        self
+    }
+
+    /// Disables clock stretching (`NOSTRETCH`) by this peripheral
+    ///
+    /// On the F4, the bit only affects the peripheral when it is addressed as a slave: instead
+    /// of holding SCL low until software has handled a byte, it signals an overrun. As master,
+    /// the peripheral generates SCL from `CCR` and `TRISE` either way, so the standard and fast
+    /// mode timings are unchanged and need no recalculation. Slaves on the bus can still
+    /// stretch the clock, which [`with_stretch_timeout`](Self::with_stretch_timeout) bounds.
+    ///
+    /// Only use this when software is always fast enough to serve the slave role, otherwise
+    /// bytes are lost.
+    pub fn with_no_stretch(self, no_stretch: bool) -> Self {
+        // the bit may only be changed while the peripheral is disabled
+        self.i2c.cr1.modify(|_, w| w.pe().clear_bit());
+        self.i2c.cr1.modify(|_, w| w.nostretch().bit(no_stretch));
+        self.i2c.cr1.modify(|_, w| w.pe().set_bit());
+        self
+    }
+
+    /// Limits how long a slave may stretch the clock while a byte is transferred.
+    ///
+    /// Transfers that wait longer than `timeout` fail with `Error::TIMEOUT`. By default the data
+    /// bytes wait as long as the slave stretches the clock, so a slave holding SCL low forever
+    /// blocks the transfer. The timeout is measured with the DWT cycle counter, which has to
+    /// be enabled (see [`DwtExt`](crate::dwt::DwtExt) or [`MonoTimer`](crate::timer::MonoTimer)).
+    pub fn with_stretch_timeout(mut self, timeout: MilliSeconds, clocks: &Clocks) -> Self {
+        let cycles = u64::from(clocks.sysclk().0) * u64::from(timeout.0) / 1_000;
+        self.stretch_timeout = cycles.min(u64::from(u32::MAX)) as u32;
+        self
+    }
+
+    /// Waits until no other master is using the bus, failing with `Error::BUSY` after `timeout`
+    ///
+    /// The peripheral flags the bus as busy from a low level on SDA or SCL until it detects a
+    /// STOP condition, so this also catches a line held low by a stuck slave. Call it before a
+    /// transfer on a multi-master bus to avoid sending a START into a transfer in progress.
+    pub fn wait_bus_idle<D: DelayUs<u32>>(
+        &mut self,
+        delay: &mut D,
+        timeout: MilliSeconds,
+    ) -> Result<(), Error> {
+        const POLL_INTERVAL_US: u32 = 10;
+        let polls = timeout.0.saturating_mul(1_000 / POLL_INTERVAL_US);
+        for _ in 0..polls {
+            if self.i2c.sr2.read().busy().bit_is_clear() {
+                return Ok(());
+            }
+            delay.delay_us(POLL_INTERVAL_US);
+        }
+        if self.i2c.sr2.read().busy().bit_is_clear() {
+            Ok(())
+        } else {
+            Err(Error::BUSY)
+        }
+    }
+
+    /// Sends a STOP condition and waits until it was transmitted, leaving the
+    /// bus idle for the next START.
+    fn release_bus(&self) {
+        self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+        // a stuck bus is reported by the next transfer
+        let _ = self.poll(|i2c| Ok(i2c.i2c.cr1.read().stop().bit_is_clear()));
+    }
+
+    fn retry<D, F>(&mut self, delay: &mut D, mut f: F) -> Result<(), Error>
+    where
+        D: DelayUs<u32>,
+        F: FnMut(&mut Self) -> Result<(), Error>,
+    {
+        let mut backoff_us = RETRY_BACKOFF_US;
+        let mut attempt = 0;
+        loop {
+            match f(self) {
+                Err(e) if matches!(e, Error::NACK | Error::BUS | Error::ARBITRATION) => {
+                    // Never leave the slave half-addressed between attempts
+                    self.release_bus();
+                    if attempt >= self.retries {
+                        return Err(e);
+                    }
+                    attempt += 1;
+                    delay.delay_us(backoff_us);
+                    backoff_us = backoff_us.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Like `Write::write`, but retries failed attempts up to the number set with
+    /// [`with_retries`](Self::with_retries).
+    ///
+    /// The delay before the first retry is 100 µs and doubles with each attempt.
+    pub fn write_with_retries<D: DelayUs<u32>, A: Into<SevenBitAddress>>(
+        &mut self,
+        delay: &mut D,
+        addr: A,
+        bytes: &[u8],
+    ) -> Result<(), Error> {
+        let addr = addr.into().get();
+        self.retry(delay, |i2c| i2c.write(addr, bytes))
+    }
+
+    /// Like `Read::read`, but retries failed attempts up to the number set with
+    /// [`with_retries`](Self::with_retries).
+    ///
+    /// The delay before the first retry is 100 µs and doubles with each attempt.
+    pub fn read_with_retries<D: DelayUs<u32>, A: Into<SevenBitAddress>>(
+        &mut self,
+        delay: &mut D,
+        addr: A,
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        let addr = addr.into().get();
+        self.retry(delay, |i2c| i2c.read(addr, buffer))
+    }
+
+    /// Like `WriteRead::write_read`, but retries failed attempts up to the number set with
+    /// [`with_retries`](Self::with_retries).
+    ///
+    /// The delay before the first retry is 100 µs and doubles with each attempt.
+    pub fn write_read_with_retries<D: DelayUs<u32>, A: Into<SevenBitAddress>>(
+        &mut self,
+        delay: &mut D,
+        addr: A,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        let addr = addr.into().get();
+        self.retry(delay, |i2c| i2c.write_read(addr, bytes, buffer))
+    }
+
+    /// Checks whether a device acknowledges `addr`, without transferring any data
+    ///
+    /// Sends a START and the address, then a STOP in any case, so a missing device does not
+    /// leave the bus hanging. If the peripheral still flags the bus as busy afterwards, it is
+    /// reset, keeping its configuration. Errors other than a NACK are returned.
+    pub fn ping<A: Into<SevenBitAddress>>(&mut self, addr: A) -> Result<bool, Error> {
+        let result = match self.write_bytes(addr.into().get(), &[]) {
+            Ok(()) => {
+                self.release_bus();
+                Ok(true)
+            }
+            Err(Error::NACK) => {
+                self.release_bus();
+                Ok(false)
+            }
+            Err(e) => {
+                if matches!(e, Error::BUS | Error::ARBITRATION) {
+                    self.release_bus();
+                }
+                Err(e)
+            }
+        };
+        self.reset_if_busy();
+        result
+    }
+
+    /// Resets the peripheral if it still flags the bus as busy after a STOP
+    ///
+    /// A glitch during a NACKed address can leave BUSY set, and the next START would then
+    /// never be generated.
+    fn reset_if_busy(&self) {
+        if self.i2c.sr2.read().busy().bit_is_set() {
+            self.software_reset();
+        }
+    }
+
+    /// Clears the state machine with a software reset (`SWRST`)
+    ///
+    /// The reset also clears the configuration, so the timing and control settings are written
+    /// back afterwards.
+    #[allow(unused_unsafe)]
+    fn software_reset(&self) {
+        // PE, SMBUS, SMBTYPE, ENARP, ENPEC, ENGC and NOSTRETCH
+        let cr1 = self.i2c.cr1.read().bits() & 0xff;
+        let cr2 = self.i2c.cr2.read().bits();
+        let ccr = self.i2c.ccr.read().bits();
+        let trise = self.i2c.trise.read().bits();
+        #[cfg(not(any(
+            feature = "stm32f405",
+            feature = "stm32f407",
+            feature = "stm32f415",
+            feature = "stm32f417"
+        )))]
+        let fltr = self.i2c.fltr.read().bits();
+
+        self.i2c.cr1.write(|w| w.swrst().set_bit());
+        self.i2c.cr1.reset();
+
+        #[cfg(not(any(
+            feature = "stm32f405",
+            feature = "stm32f407",
+            feature = "stm32f415",
+            feature = "stm32f417"
+        )))]
+        self.i2c.fltr.write(|w| unsafe { w.bits(fltr) });
+        self.i2c.cr2.write(|w| unsafe { w.bits(cr2) });
+        self.i2c.ccr.write(|w| unsafe { w.bits(ccr) });
+        self.i2c.trise.write(|w| unsafe { w.bits(trise) });
+        self.i2c.cr1.write(|w| unsafe { w.bits(cr1) });
+    }
+
+    /// Returns the addresses that are acknowledged, out of the non-reserved ones
+    /// (`0x08` to `0x77`)
+    ///
+    /// Addresses failing with a bus error are skipped.
+    pub fn scan(&mut self) -> impl Iterator<Item = u8> + '_ {
+        (0x08..=0x77).filter(move |&addr| self.ping(addr) == Ok(true))
+    }
+
+    /// Like [`scan`](Self::scan), but stores the acknowledged addresses in `found`
+    ///
+    /// Returns the number of addresses stored. The scan stops early once `found` is full.
+    pub fn scan_into(&mut self, found: &mut [u8]) -> usize {
+        let mut count = 0;
+        for (slot, addr) in found.iter_mut().zip(self.scan()) {
+            *slot = addr;
+            count += 1;
+        }
+        count
+    }
+
+    /// Writes `bytes` to the slave at `addr`, which can be a 10-bit address
+    pub fn write_addr<A: Into<Address>>(&mut self, addr: A, bytes: &[u8]) -> Result<(), Error> {
+        match addr.into() {
+            Address::SevenBit(addr) => self.write(addr, bytes),
+            Address::TenBit(addr) => {
+                self.start_10bit(addr)?;
+                for c in bytes {
+                    self.send_byte(*c)?;
+                }
+                self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                self.poll(|i2c| Ok(i2c.i2c.cr1.read().stop().bit_is_clear()))
+            }
+        }
+    }
+
+    /// Reads `buffer` from the slave at `addr`, which can be a 10-bit address
+    pub fn read_addr<A: Into<Address>>(&mut self, addr: A, buffer: &mut [u8]) -> Result<(), Error> {
+        match addr.into() {
+            Address::SevenBit(addr) => self.read(addr, buffer),
+            Address::TenBit(addr) => {
+                let (last, buffer) = buffer.split_last_mut().ok_or(Error::OVERRUN)?;
+                self.start_10bit(addr)?;
+                self.restart_read_10bit(addr)?;
+                self.recv_bytes(buffer, last)
+            }
+        }
+    }
+
+    /// Writes `bytes` to the slave at `addr`, which can be a 10-bit address, then reads
+    /// `buffer` after a repeated START
+    pub fn write_read_addr<A: Into<Address>>(
+        &mut self,
+        addr: A,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        match addr.into() {
+            Address::SevenBit(addr) => self.write_read(addr, bytes, buffer),
+            Address::TenBit(addr) => {
+                let (last, buffer) = buffer.split_last_mut().ok_or(Error::OVERRUN)?;
+                self.start_10bit(addr)?;
+                for c in bytes {
+                    self.send_byte(*c)?;
+                }
+                self.restart_read_10bit(addr)?;
+                self.recv_bytes(buffer, last)
+            }
+        }
+    }
+
+    /// Like `WriteRead::write_read`, but reads a packet error checking byte after `buffer`
+    /// and compares it with the CRC computed over the whole transaction
+    ///
+    /// Fails with `Error::CRC` on a mismatch. PEC has to be enabled with
+    /// [`with_pec`](Self::with_pec).
+    ///
+    /// The I2C peripheral of the F4 has no byte counter that could append or check the PEC by
+    /// itself, as the one of newer families does: the PEC bit has to be set while the byte
+    /// before the PEC is received, which this method does along with the NACK and STOP.
+    pub fn write_read_pec<A: Into<SevenBitAddress>>(
+        &mut self,
+        addr: A,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        debug_assert!(
+            self.i2c.cr1.read().enpec().bit_is_set(),
+            "PEC is not enabled"
+        );
+        let addr = addr.into().get();
+
+        self.write_bytes(addr, bytes)?;
+
+        // Send a repeated START condition and set ACK bit
+        self.i2c
+            .cr1
+            .modify(|_, w| w.start().set_bit().ack().set_bit());
+        self.poll(|i2c| Ok(i2c.check_and_clear_error_flags()?.sb().bit_is_set()))?;
+
+        self.i2c
+            .dr
+            .write(|w| unsafe { w.bits((u32::from(addr) << 1) + 1) });
+        self.poll(|i2c| Ok(i2c.check_and_clear_error_flags()?.addr().bit_is_set()))?;
+
+        // Clear condition by reading SR2
+        self.i2c.sr2.read();
+
+        for c in buffer {
+            *c = self.recv_byte()?;
+        }
+
+        // The next byte is the PEC, answered with a NACK and followed by a STOP
+        self.i2c
+            .cr1
+            .modify(|_, w| w.ack().clear_bit().pec().set_bit().stop().set_bit());
+
+        // The received PEC is compared by the peripheral, which sets PECERR on a mismatch
+        self.recv_byte()?;
+        self.poll(|i2c| Ok(i2c.i2c.cr1.read().stop().bit_is_clear()))?;
+        self.check_and_clear_error_flags()?;
+
+        Ok(())
+    }
+
+    /// Sends a START and both header bytes of the 10-bit address `addr`, for writing
+    fn start_10bit(&self, addr: u16) -> Result<(), Error> {
+        debug_assert!(
+            addr < 0x400,
+            "I2C address {:#05x} does not fit into 10 bits",
+            addr
+        );
+
+        // Send a START condition
+        self.i2c.cr1.modify(|_, w| w.start().set_bit());
+
+        // Wait until START condition was generated
+        self.poll(|i2c| Ok(i2c.check_and_clear_error_flags()?.sb().bit_is_set()))?;
+
+        // Header with the two high address bits, write direction
+        let header = 0xf0 | ((addr >> 7) & 0x06) as u8;
+        self.i2c.dr.write(|w| unsafe { w.bits(u32::from(header)) });
+        self.poll(|i2c| Ok(i2c.check_and_clear_error_flags()?.add10().bit_is_set()))?;
+
+        // Low eight address bits
+        self.i2c
+            .dr
+            .write(|w| unsafe { w.bits(u32::from(addr as u8)) });
+        self.poll(|i2c| Ok(i2c.check_and_clear_error_flags()?.addr().bit_is_set()))?;
+
+        // Clear condition by reading SR2
+        self.i2c.sr2.read();
+
+        Ok(())
+    }
+
+    /// Sends a repeated START and the header of the 10-bit address `addr`, for reading
+    ///
+    /// The slave is still selected by the preceding [`start_10bit`](Self::start_10bit), so
+    /// the low address byte is not sent again.
+    fn restart_read_10bit(&self, addr: u16) -> Result<(), Error> {
+        // Send a repeated START condition and set ACK bit
+        self.i2c
+            .cr1
+            .modify(|_, w| w.start().set_bit().ack().set_bit());
+
+        // Wait until START condition was generated
+        self.poll(|i2c| Ok(i2c.check_and_clear_error_flags()?.sb().bit_is_set()))?;
+
+        // Header with the two high address bits, read direction
+        let header = 0xf1 | ((addr >> 7) & 0x06) as u8;
+        self.i2c.dr.write(|w| unsafe { w.bits(u32::from(header)) });
+        self.poll(|i2c| Ok(i2c.check_and_clear_error_flags()?.addr().bit_is_set()))
+    }
+
+    /// Receives `buffer` and `last` once the slave acknowledged its address for reading,
+    /// ending with a NACK and a STOP
+    fn recv_bytes(&self, buffer: &mut [u8], last: &mut u8) -> Result<(), Error> {
+        // Clear condition by reading SR2
+        self.i2c.sr2.read();
+
+        // Receive bytes into buffer
+        for c in buffer {
+            *c = self.recv_byte()?;
+        }
+
+        // Prepare to send NACK then STOP after next byte
+        self.i2c
+            .cr1
+            .modify(|_, w| w.ack().clear_bit().stop().set_bit());
+
+        // Receive last byte
+        *last = self.recv_byte()?;
+
+        // Wait for the STOP to be sent.
+        self.poll(|i2c| Ok(i2c.i2c.cr1.read().stop().bit_is_clear()))
+    }
+
+    /// Polls `f` until it returns `true`, failing with `Error::TIMEOUT` after `POLL_LIMIT` tries
+    ///
+    /// This bounds the START, address and STOP phases, which wait forever on a bus that is
+    /// stuck, see [`release_and_recover`](I2c::release_and_recover). The data bytes are bounded
+    /// by [`wait_for_flag`](Self::wait_for_flag).
+    fn poll<F>(&self, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(&Self) -> Result<bool, Error>,
+    {
+        for _ in 0..POLL_LIMIT {
+            if f(self)? {
+                return Ok(());
+            }
+        }
+        Err(Error::TIMEOUT)
+    }
+
+    /// Polls SR1 until `f` returns `true`, failing once the clock stretch timeout has elapsed.
+    ///
+    /// Without a stretch timeout it waits as long as the slave stretches the clock. A fixed
+    /// number of polls like [`poll`](Self::poll) would depend on the core clock and is far too
+    /// short for slaves that stretch for milliseconds, like EEPROMs finishing a page write.
+    fn wait_for_flag<F>(&self, f: F) -> Result<(), Error>
+    where
+        F: Fn(&i2c1::sr1::R) -> bool,
+    {
+        let start = DWT::get_cycle_count();
+        loop {
+            if f(&self.check_and_clear_error_flags()?) {
+                return Ok(());
+            }
+            if self.stretch_timeout != 0
+                && DWT::get_cycle_count().wrapping_sub(start) > self.stretch_timeout
+            {
+                return Err(Error::TIMEOUT);
+            }
+        }
+    }
+
     fn check_and_clear_error_flags(&self) -> Result<i2c1::sr1::R, Error> {
         // Note that flags should only be cleared once they have been registered. If flags are
         // cleared otherwise, there may be an inherent race condition and flags may be missed.
@@ -280,17 +991,15 @@ where
         self.i2c.cr1.modify(|_, w| w.start().set_bit());
 
         // Wait until START condition was generated
-        while self.check_and_clear_error_flags()?.sb().bit_is_clear() {}
+        self.poll(|i2c| Ok(i2c.check_and_clear_error_flags()?.sb().bit_is_set()))?;
 
         // Also wait until signalled we're master and everything is waiting for us
-        loop {
-            self.check_and_clear_error_flags()?;
+        self.poll(|i2c| {
+            i2c.check_and_clear_error_flags()?;
 
-            let sr2 = self.i2c.sr2.read();
-            if !(sr2.msl().bit_is_clear() && sr2.busy().bit_is_clear()) {
-                break;
-            }
-        }
+            let sr2 = i2c.i2c.sr2.read();
+            Ok(!(sr2.msl().bit_is_clear() && sr2.busy().bit_is_clear()))
+        })?;
 
         // Set up current address, we're trying to talk to
         self.i2c
@@ -298,15 +1007,13 @@ where
             .write(|w| unsafe { w.bits(u32::from(addr) << 1) });
 
         // Wait until address was sent
-        loop {
+        self.poll(|i2c| {
             // Check for any I2C errors. If a NACK occurs, the ADDR bit will never be set.
-            let sr1 = self.check_and_clear_error_flags()?;
+            let sr1 = i2c.check_and_clear_error_flags()?;
 
             // Wait for the address to be acknowledged
-            if sr1.addr().bit_is_set() {
-                break;
-            }
-        }
+            Ok(sr1.addr().bit_is_set())
+        })?;
 
         // Clear condition by reading SR2
         self.i2c.sr2.read();
@@ -323,27 +1030,21 @@ where
     fn send_byte(&self, byte: u8) -> Result<(), Error> {
         // Wait until we're ready for sending
         // Check for any I2C errors. If a NACK occurs, the ADDR bit will never be set.
-        while self.check_and_clear_error_flags()?.tx_e().bit_is_clear() {}
+        self.wait_for_flag(|sr1| sr1.tx_e().bit_is_set())?;
 
         // Push out a byte of data
         self.i2c.dr.write(|w| unsafe { w.bits(u32::from(byte)) });
 
         // Wait until byte is transferred
         // Check for any potential error conditions.
-        while self.check_and_clear_error_flags()?.btf().bit_is_clear() {}
+        self.wait_for_flag(|sr1| sr1.btf().bit_is_set())?;
 
         Ok(())
     }
 
     fn recv_byte(&self) -> Result<u8, Error> {
-        loop {
-            // Check for any potential error conditions.
-            self.check_and_clear_error_flags()?;
-
-            if self.i2c.sr1.read().rx_ne().bit_is_set() {
-                break;
-            }
-        }
+        // Check for any potential error conditions.
+        self.wait_for_flag(|sr1| sr1.rx_ne().bit_is_set())?;
 
         let value = self.i2c.dr.read().bits() as u8;
         Ok(value)
@@ -377,7 +1078,7 @@ where
         self.i2c.cr1.modify(|_, w| w.stop().set_bit());
 
         // Wait for STOP condition to transmit.
-        while self.i2c.cr1.read().stop().bit_is_set() {}
+        self.poll(|i2c| Ok(i2c.i2c.cr1.read().stop().bit_is_clear()))?;
 
         // Fallthrough is success
         Ok(())
@@ -398,15 +1099,15 @@ where
                 .modify(|_, w| w.start().set_bit().ack().set_bit());
 
             // Wait until START condition was generated
-            while self.i2c.sr1.read().sb().bit_is_clear() {}
+            self.poll(|i2c| Ok(i2c.check_and_clear_error_flags()?.sb().bit_is_set()))?;
 
             // Also wait until signalled we're master and everything is waiting for us
-            loop {
-                let sr2 = self.i2c.sr2.read();
-                if !(sr2.msl().bit_is_clear() && sr2.busy().bit_is_clear()) {
-                    break;
-                }
-            }
+            self.poll(|i2c| {
+                i2c.check_and_clear_error_flags()?;
+
+                let sr2 = i2c.i2c.sr2.read();
+                Ok(!(sr2.msl().bit_is_clear() && sr2.busy().bit_is_clear()))
+            })?;
 
             // Set up current address, we're trying to talk to
             self.i2c
@@ -414,31 +1115,12 @@ where
                 .write(|w| unsafe { w.bits((u32::from(addr) << 1) + 1) });
 
             // Wait until address was sent
-            loop {
-                self.check_and_clear_error_flags()?;
-                if self.i2c.sr1.read().addr().bit_is_set() {
-                    break;
-                }
-            }
-
-            // Clear condition by reading SR2
-            self.i2c.sr2.read();
-
-            // Receive bytes into buffer
-            for c in buffer {
-                *c = self.recv_byte()?;
-            }
-
-            // Prepare to send NACK then STOP after next byte
-            self.i2c
-                .cr1
-                .modify(|_, w| w.ack().clear_bit().stop().set_bit());
-
-            // Receive last byte
-            *last = self.recv_byte()?;
+            self.poll(|i2c| {
+                i2c.check_and_clear_error_flags()?;
+                Ok(i2c.i2c.sr1.read().addr().bit_is_set())
+            })?;
 
-            // Wait for the STOP to be sent.
-            while self.i2c.cr1.read().stop().bit_is_set() {}
+            self.recv_bytes(buffer, last)?;
 
             // Fallthrough is success
             Ok(())
@@ -0,0 +1,186 @@
+//! DMA transfers of the data bytes of I2C transactions
+//!
+//! The address phase is handled by the CPU as in the blocking transfers, a DMA stream moves the
+//! data bytes between memory and the data register. The DMA can't end a transaction though:
+//! the STOP condition is sent by [`I2cDma::finish_write`] or [`I2cDma::finish_read`], to be
+//! called once the stream reports transfer complete, usually from its interrupt.
+//!
+//! The `pac::I2Cx` peripherals can already be used as DMA targets, but the driver owns its
+//! peripheral for the address phase and the STOP condition. [`Tx`] and [`Rx`] are zero-sized
+//! stand-ins pointing at the same data register, and accept the streams and channels of the
+//! `I2Cx_TX` and `I2Cx_RX` requests respectively.
+//!
+//! Writing a page to an EEPROM at address `0x50`:
+//!
+//! ```ignore
+//! let mut i2c = i2c.use_dma();
+//! let mut transfer = Transfer::init_memory_to_peripheral(
+//!     streams.6,
+//!     i2c.tx(),
+//!     page,
+//!     None,
+//!     DmaConfig::default().memory_increment(true),
+//! );
+//! transfer.start(|_| {});
+//! i2c.start_write(0x50)?;
+//! // in the transfer complete interrupt of the stream
+//! i2c.finish_write()?;
+//! ```
+
+use core::marker::PhantomData;
+
+use super::{Error, I2c, I2cCommon, Instance, SevenBitAddress};
+use crate::dma::{
+    traits::{DMASet, PeriAddress},
+    MemoryToPeripheral, PeripheralToMemory,
+};
+
+/// I2C whose data bytes are transferred by DMA, created by [`I2c::use_dma`]
+pub struct I2cDma<I2C: Instance, PINS> {
+    i2c: I2c<I2C, PINS>,
+}
+
+/// DMA transmit token of an I2C, pointing at its data register
+///
+/// `I2Cx_TX` request, see [`DMASet`].
+pub struct Tx<I2C> {
+    i2c: PhantomData<I2C>,
+}
+
+/// DMA receive token of an I2C, pointing at its data register
+///
+/// `I2Cx_RX` request, see [`DMASet`].
+pub struct Rx<I2C> {
+    i2c: PhantomData<I2C>,
+}
+
+impl<I2C, PINS> I2c<I2C, PINS>
+where
+    I2C: Instance,
+{
+    /// Switches to transfers of the data bytes by DMA
+    pub fn use_dma(self) -> I2cDma<I2C, PINS> {
+        I2cDma { i2c: self }
+    }
+}
+
+impl<I2C, PINS> I2cDma<I2C, PINS>
+where
+    I2C: Instance,
+{
+    /// Returns the token to set up a memory to peripheral stream with
+    pub fn tx(&self) -> Tx<I2C> {
+        Tx { i2c: PhantomData }
+    }
+
+    /// Returns the token to set up a peripheral to memory stream with
+    pub fn rx(&self) -> Rx<I2C> {
+        Rx { i2c: PhantomData }
+    }
+
+    /// Addresses the slave at `addr` for writing and lets the DMA send the data
+    ///
+    /// The stream has to be started before, it is served as soon as the address is
+    /// acknowledged.
+    pub fn start_write<A: Into<SevenBitAddress>>(&mut self, addr: A) -> Result<(), Error> {
+        self.i2c
+            .i2c
+            .cr2
+            .modify(|_, w| w.dmaen().set_bit().last().clear_bit());
+        self.i2c.write_bytes(addr.into().get(), &[])
+    }
+
+    /// Sends the STOP condition after the DMA has written the last byte
+    ///
+    /// Call this once the stream reports transfer complete. It waits for the last byte to leave
+    /// the data register before ending the transaction.
+    pub fn finish_write(&mut self) -> Result<(), Error> {
+        let result = self.i2c.wait_for_flag(|sr1| sr1.btf().bit_is_set());
+        self.end()?;
+        result
+    }
+
+    /// Addresses the slave at `addr` for reading and lets the DMA receive the data
+    ///
+    /// `LAST` is set, so the peripheral answers the last byte of the stream with a NACK. The
+    /// stream has to be started before and has to transfer at least two bytes.
+    pub fn start_read<A: Into<SevenBitAddress>>(&mut self, addr: A) -> Result<(), Error> {
+        let i2c = &self.i2c;
+        i2c.i2c
+            .cr2
+            .modify(|_, w| w.dmaen().set_bit().last().set_bit());
+
+        // Send a START condition and set ACK bit
+        i2c.i2c
+            .cr1
+            .modify(|_, w| w.start().set_bit().ack().set_bit());
+        i2c.poll(|i2c| Ok(i2c.check_and_clear_error_flags()?.sb().bit_is_set()))?;
+
+        // Set up current address, we're trying to talk to
+        let addr = addr.into().get();
+        i2c.i2c
+            .dr
+            .write(|w| unsafe { w.bits((u32::from(addr) << 1) + 1) });
+        i2c.poll(|i2c| Ok(i2c.check_and_clear_error_flags()?.addr().bit_is_set()))?;
+
+        // Clear condition by reading SR2, the DMA takes over from here
+        i2c.i2c.sr2.read();
+        Ok(())
+    }
+
+    /// Sends the STOP condition after the DMA has received the last byte
+    ///
+    /// Call this once the stream reports transfer complete.
+    pub fn finish_read(&mut self) -> Result<(), Error> {
+        self.end()
+    }
+
+    /// Sends a STOP and disables the DMA requests
+    fn end(&mut self) -> Result<(), Error> {
+        let i2c = &self.i2c;
+        i2c.i2c.cr1.modify(|_, w| w.stop().set_bit());
+        i2c.i2c
+            .cr2
+            .modify(|_, w| w.dmaen().clear_bit().last().clear_bit());
+        i2c.poll(|i2c| Ok(i2c.i2c.cr1.read().stop().bit_is_clear()))
+    }
+
+    /// Returns to blocking transfers
+    pub fn release(self) -> I2c<I2C, PINS> {
+        self.i2c
+            .i2c
+            .cr2
+            .modify(|_, w| w.dmaen().clear_bit().last().clear_bit());
+        self.i2c
+    }
+}
+
+unsafe impl<I2C: Instance> PeriAddress for Tx<I2C> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        unsafe { &(*I2C::ptr()).dr as *const _ as u32 }
+    }
+
+    type MemSize = u8;
+}
+
+unsafe impl<I2C: Instance> PeriAddress for Rx<I2C> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        unsafe { &(*I2C::ptr()).dr as *const _ as u32 }
+    }
+
+    type MemSize = u8;
+}
+
+// The tokens use the streams and channels of the peripheral itself, restricted to the
+// direction of their request
+unsafe impl<I2C, STREAM, const CHANNEL: u8> DMASet<STREAM, MemoryToPeripheral, CHANNEL> for Tx<I2C> where
+    I2C: DMASet<STREAM, MemoryToPeripheral, CHANNEL>
+{
+}
+
+unsafe impl<I2C, STREAM, const CHANNEL: u8> DMASet<STREAM, PeripheralToMemory, CHANNEL> for Rx<I2C> where
+    I2C: DMASet<STREAM, PeripheralToMemory, CHANNEL>
+{
+}
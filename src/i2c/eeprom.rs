@@ -0,0 +1,84 @@
+//! Page writes to I2C EEPROMs (24Cxx and compatible)
+//!
+//! An EEPROM accepts at most one page per write, and ignores its address while the write cycle
+//! runs. [`I2c::eeprom_write`] splits the data on page boundaries and waits for each write cycle
+//! by polling the device until it acknowledges its address again.
+
+use embedded_hal::blocking::delay::DelayUs;
+
+use super::{Error, I2c, I2cCommon, Instance, SevenBitAddress};
+
+/// Delay between two polls for the end of a write cycle, in microseconds
+const ACK_POLL_INTERVAL_US: u32 = 100;
+/// Polls before giving up on a write cycle, 20 ms in total
+const ACK_POLL_ATTEMPTS: u32 = 200;
+
+/// Size of the memory address sent before the data
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum AddressSize {
+    /// 8-bit memory address, for devices up to 2 kbit
+    OneByte,
+    /// 16-bit memory address, big-endian
+    TwoBytes,
+}
+
+impl<I2C, PINS> I2c<I2C, PINS>
+where
+    I2C: Instance,
+{
+    /// Writes `data` to the EEPROM at `addr`, starting at `mem_addr`
+    ///
+    /// The data is split so that no write crosses a boundary of `page_size` bytes, and each
+    /// write cycle is awaited before the next page is sent. Fails with `Error::TIMEOUT` if the
+    /// device does not finish a write cycle within 20 ms.
+    pub fn eeprom_write<D, A>(
+        &mut self,
+        delay: &mut D,
+        addr: A,
+        address_size: AddressSize,
+        mut mem_addr: u16,
+        mut data: &[u8],
+        page_size: u16,
+    ) -> Result<(), Error>
+    where
+        D: DelayUs<u32>,
+        A: Into<SevenBitAddress>,
+    {
+        assert!(page_size > 0);
+        let addr = addr.into().get();
+
+        while !data.is_empty() {
+            let room = usize::from(page_size - mem_addr % page_size);
+            let (page, rest) = data.split_at(room.min(data.len()));
+
+            let [hi, lo] = mem_addr.to_be_bytes();
+            let result = match address_size {
+                AddressSize::OneByte => self.write_bytes(addr, &[lo]),
+                AddressSize::TwoBytes => self.write_bytes(addr, &[hi, lo]),
+            }
+            .and_then(|_| page.iter().try_for_each(|b| self.send_byte(*b)));
+            self.release_bus();
+            result?;
+
+            self.eeprom_wait(delay, addr)?;
+
+            mem_addr = mem_addr.wrapping_add(page.len() as u16);
+            data = rest;
+        }
+
+        Ok(())
+    }
+
+    /// Polls the device until it acknowledges its address, which ends the write cycle
+    fn eeprom_wait<D: DelayUs<u32>>(&mut self, delay: &mut D, addr: u8) -> Result<(), Error> {
+        for _ in 0..ACK_POLL_ATTEMPTS {
+            let result = self.write_bytes(addr, &[]);
+            self.release_bus();
+            match result {
+                Err(Error::NACK) => delay.delay_us(ACK_POLL_INTERVAL_US),
+                result => return result,
+            }
+        }
+        Err(Error::TIMEOUT)
+    }
+}
@@ -118,8 +118,12 @@ pub mod crc32;
 #[cfg(all(feature = "device-selected", feature = "dac"))]
 pub mod dac;
 #[cfg(feature = "device-selected")]
+pub mod dbgmcu;
+#[cfg(feature = "device-selected")]
 pub mod delay;
 #[cfg(feature = "device-selected")]
+pub mod error;
+#[cfg(feature = "device-selected")]
 #[cfg(feature = "fmpi2c1")]
 pub mod fmpi2c;
 #[cfg(feature = "device-selected")]
@@ -146,6 +150,8 @@ pub mod dma;
 pub mod dwt;
 #[cfg(feature = "device-selected")]
 pub mod flash;
+#[cfg(feature = "device-selected")]
+pub mod freq_counter;
 #[cfg(all(
     feature = "device-selected",
     feature = "fsmc_lcd",
@@ -1,11 +1,14 @@
 use crate::{
     bb, hal as pwm,
+    rcc::{BusTimerClock, Clocks},
     time::Hertz,
     timer::{General, Timer},
 };
 use cast::u16;
 use core::{marker::PhantomData, mem::MaybeUninit};
 
+pub mod buzzer;
+
 pub trait Pins<TIM, P> {
     const C1: bool = false;
     const C2: bool = false;
@@ -15,6 +18,68 @@ pub trait Pins<TIM, P> {
 }
 pub use crate::timer::{CPin, C1, C2, C3, C4};
 
+/// Returns the max duty `Timer::pwm` configures for a timer clocked at `clk`
+/// running at `freq`.
+///
+/// As this is a `const fn`, it can be used to build duty lookup tables at
+/// compile time when the clock configuration is known in advance.
+///
+/// `freq` is valid from `clk / 2^32` up to `clk`. A higher `freq` saturates at the shortest
+/// period like `SetFrequency::set_frequency` does, a zero `freq` gives the longest period,
+/// and a period of 65536 ticks saturates at `u16::MAX`.
+pub const fn max_duty(clk: Hertz, freq: Hertz) -> u16 {
+    let ticks = if freq.0 == 0 {
+        u32::MAX
+    } else {
+        clk.0 / freq.0
+    };
+    let ticks = if ticks == 0 { 1 } else { ticks };
+    let psc = (ticks - 1) / (1 << 16);
+    let arr = ticks / (psc + 1);
+    if arr > u16::MAX as u32 {
+        u16::MAX
+    } else {
+        arr as u16
+    }
+}
+
+/// The requested dead-time is longer than the dead-time generator can insert
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct DeadTimeTooLong;
+
+/// Encodes a dead-time of `ticks` DTS periods as `DTG` value, rounding up to the next
+/// value the generator can represent.
+fn dead_time_dtg(ticks: u32) -> Option<u8> {
+    match ticks {
+        0..=127 => Some(ticks as u8),
+        128..=254 => Some(0b1000_0000 | ((ticks + 1) / 2 - 64) as u8),
+        255..=504 => Some(0b1100_0000 | ((ticks + 7) / 8 - 32) as u8),
+        505..=1008 => Some(0b1110_0000 | ((ticks + 15) / 16 - 32) as u8),
+        _ => None,
+    }
+}
+
+/// Decodes a `DTG` value into the dead-time in DTS periods
+fn dead_time_ticks(dtg: u8) -> u32 {
+    let dtg = u32::from(dtg);
+    match dtg >> 5 {
+        0..=3 => dtg,
+        4 | 5 => (64 + (dtg & 0x3f)) * 2,
+        6 => (32 + (dtg & 0x1f)) * 8,
+        _ => (32 + (dtg & 0x1f)) * 16,
+    }
+}
+
+/// Changing the frequency of a running PWM
+pub trait SetFrequency {
+    /// Reprograms the period of the timer to `freq`, restarting the counter
+    ///
+    /// This affects all channels of the timer. The max duty changes with the period, so duties
+    /// have to be set again relative to the new `get_max_duty`. Frequencies above the timer
+    /// clock give the fastest period the timer can produce.
+    fn set_frequency(&mut self, freq: Hertz, clocks: &Clocks);
+}
+
 pub struct PwmChannel<TIM, CHANNEL> {
     _channel: PhantomData<CHANNEL>,
     _tim: PhantomData<TIM>,
@@ -102,6 +167,55 @@ macro_rules! pwm_pin {
             pub fn set_duty(&mut self, duty: u16) {
                 unsafe { (*<$TIMX>::ptr()).$ccr.write(|w| w.bits(duty.into())) }
             }
+
+            /// Sets the duty to `num / den` of the max duty, saturating at 100%
+            ///
+            /// The intermediate product is computed in 32 bits, so it can't overflow. A zero `den`
+            /// saturates as well and sets the max duty.
+            #[inline]
+            pub fn set_duty_fraction(&mut self, num: u16, den: u16) {
+                let max_duty = u32::from(self.get_max_duty());
+                let duty = (u32::from(num) * max_duty)
+                    .checked_div(u32::from(den))
+                    .unwrap_or(max_duty)
+                    .min(max_duty);
+                self.set_duty(duty as u16)
+            }
+
+            /// Applies the preloaded duty and period right away
+            ///
+            /// Both are preloaded and otherwise only take effect at the next update event. This
+            /// generates an update event without raising the update interrupt, which restarts
+            /// the counter of all channels of the timer.
+            #[inline]
+            pub fn commit(&mut self) {
+                //NOTE(unsafe) the update event only reloads the shadow registers
+                unsafe {
+                    let tim = &*<$TIMX>::ptr();
+                    tim.cr1.modify(|_, w| w.urs().set_bit());
+                    tim.egr.write(|w| w.ug().set_bit());
+                    tim.cr1.modify(|_, w| w.urs().clear_bit());
+                }
+            }
+        }
+
+        impl SetFrequency for PwmChannel<$TIMX, $C> {
+            fn set_frequency(&mut self, freq: Hertz, clocks: &Clocks) {
+                // frequencies above the timer clock saturate at the fastest period
+                let ticks = (<$TIMX as BusTimerClock>::timer_clock(clocks).0 / freq.0).max(1);
+                let psc = (ticks - 1) / (1 << 16);
+                let arr = ticks / (psc + 1);
+                //NOTE(unsafe) the period is shared by all channels, which only read it
+                unsafe {
+                    let tim = &*<$TIMX>::ptr();
+                    tim.psc.write(|w| w.bits(psc));
+                    tim.arr.write(|w| w.bits(arr));
+                    // load the preloaded registers without raising an update interrupt
+                    tim.cr1.modify(|_, w| w.urs().set_bit());
+                    tim.egr.write(|w| w.ug().set_bit());
+                    tim.cr1.modify(|_, w| w.urs().clear_bit());
+                }
+            }
         }
 
         impl pwm::PwmPin for PwmChannel<$TIMX, $C> {
@@ -125,6 +239,175 @@ macro_rules! pwm_pin {
     };
 }
 
+macro_rules! pwm_complementary {
+    ($TIMX:ty: $($C:ty, $bit:literal, $nbit:literal;)+) => {
+        $(
+            impl PwmChannel<$TIMX, $C> {
+                /// Enables the main and the complementary output of the channel together
+                ///
+                /// Both outputs are driven from the same duty set with `set_duty`: the
+                /// complementary output is the inverted signal, and the dead-time set with
+                /// `set_dead_time` is inserted before each rising edge of either output.
+                #[inline]
+                pub fn enable_complementary(&mut self) {
+                    cortex_m::interrupt::free(|_| {
+                        //NOTE(unsafe) read-modify-write of this channel's bits in a critical section
+                        unsafe {
+                            (*<$TIMX>::ptr())
+                                .ccer
+                                .modify(|r, w| w.bits(r.bits() | (1 << $bit) | (1 << $nbit)))
+                        }
+                    })
+                }
+
+                /// Disables the main and the complementary output of the channel together
+                #[inline]
+                pub fn disable_complementary(&mut self) {
+                    cortex_m::interrupt::free(|_| {
+                        //NOTE(unsafe) read-modify-write of this channel's bits in a critical section
+                        unsafe {
+                            (*<$TIMX>::ptr())
+                                .ccer
+                                .modify(|r, w| w.bits(r.bits() & !((1 << $bit) | (1 << $nbit))))
+                        }
+                    })
+                }
+
+                /// Sets the raw dead-time generator value (`DTG`)
+                ///
+                /// The dead-time is shared by all channels of the timer.
+                #[inline]
+                pub fn set_dead_time(&mut self, dtg: u8) {
+                    cortex_m::interrupt::free(|_| {
+                        //NOTE(unsafe) read-modify-write of the DTG bits in a critical section
+                        unsafe {
+                            (*<$TIMX>::ptr())
+                                .bdtr
+                                .modify(|r, w| w.bits((r.bits() & !0xff) | u32::from(dtg)))
+                        }
+                    })
+                }
+
+                /// Sets the dead-time in nanoseconds and returns the dead-time actually inserted
+                ///
+                /// The generator only supports a limited set of values, so the request is
+                /// rounded up to the next one. Fails if `ns` is beyond the longest dead-time
+                /// available at the current timer clock, leaving the dead-time unchanged.
+                pub fn set_dead_time_ns(
+                    &mut self,
+                    ns: u32,
+                    clocks: &Clocks,
+                ) -> Result<u32, DeadTimeTooLong> {
+                    let dts = Self::dts_clock(clocks);
+                    let ticks = (u64::from(ns) * u64::from(dts) + 999_999_999) / 1_000_000_000;
+                    let dtg = if ticks > u64::from(u32::MAX) {
+                        None
+                    } else {
+                        dead_time_dtg(ticks as u32)
+                    }
+                    .ok_or(DeadTimeTooLong)?;
+                    self.set_dead_time(dtg);
+                    Ok(self.dead_time_ns(clocks))
+                }
+
+                /// Returns the dead-time currently inserted, in nanoseconds
+                pub fn dead_time_ns(&self, clocks: &Clocks) -> u32 {
+                    //NOTE(unsafe) atomic read with no side effects
+                    let dtg = unsafe { (*<$TIMX>::ptr()).bdtr.read().dtg().bits() };
+                    let ticks = u64::from(dead_time_ticks(dtg));
+                    (ticks * 1_000_000_000 / u64::from(Self::dts_clock(clocks))) as u32
+                }
+
+                /// Frequency of the dead-time generator, the timer clock divided by `CKD`
+                fn dts_clock(clocks: &Clocks) -> u32 {
+                    //NOTE(unsafe) atomic read with no side effects
+                    let ckd = unsafe { (*<$TIMX>::ptr()).cr1.read().ckd().bits() };
+                    <$TIMX as BusTimerClock>::timer_clock(clocks).0 >> ckd
+                }
+            }
+        )+
+    };
+}
+
+pwm_complementary!(crate::pac::TIM1:
+    C1, 0, 2;
+    C2, 4, 6;
+    C3, 8, 10;
+);
+
+#[cfg(feature = "tim8")]
+pwm_complementary!(crate::pac::TIM8:
+    C1, 0, 2;
+    C2, 4, 6;
+    C3, 8, 10;
+);
+
+/// Active level of the external trigger input (ETR)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EtrPolarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+macro_rules! ocref_clear {
+    (@channels $TIMX:ty: $($C:ty, $ccmr:ident, $bit:literal;)+) => {
+        $(
+            impl PwmChannel<$TIMX, $C> {
+                /// Lets the OCREF clear signal drive the output of this channel inactive
+                /// (`OCxCE`), see `Timer::enable_ocref_clear`
+                #[inline]
+                pub fn set_ocref_clear(&mut self, enable: bool) {
+                    //NOTE(unsafe) atomic write with no side effects
+                    unsafe { bb::write((*<$TIMX>::ptr()).$ccmr(), $bit, enable) }
+                }
+            }
+        )+
+    };
+    ($($TIMX:ty,)+) => {
+        $(
+            impl Timer<$TIMX> {
+                /// Makes the external trigger input (ETR) the OCREF clear signal of the timer
+                ///
+                /// While the ETR is active, every channel with OCREF clear enabled drives its
+                /// output inactive until the next update event, i.e. for the rest of the PWM
+                /// period. With an over-current comparator on ETR, this limits the current cycle
+                /// by cycle without software involvement.
+                ///
+                /// `filter` is the raw `ETF` value (0 to 15). The ETR pin has to be configured in
+                /// its alternate function mode.
+                #[allow(unused_unsafe)]
+                pub fn enable_ocref_clear(&mut self, polarity: EtrPolarity, filter: u8) {
+                    self.tim.smcr.modify(|_, w| unsafe {
+                        w.etf()
+                            .bits(filter & 0xf)
+                            .etps()
+                            .bits(0)
+                            .ece()
+                            .clear_bit()
+                            .etp()
+                            .bit(polarity == EtrPolarity::ActiveLow)
+                    });
+                }
+            }
+
+            ocref_clear!(@channels $TIMX:
+                C1, ccmr1_output, 7;
+                C2, ccmr1_output, 15;
+                C3, ccmr2_output, 7;
+                C4, ccmr2_output, 15;
+            );
+        )+
+    };
+}
+
+ocref_clear!(crate::pac::TIM1, crate::pac::TIM5,);
+
+#[cfg(feature = "tim2")]
+ocref_clear!(crate::pac::TIM2, crate::pac::TIM3, crate::pac::TIM4,);
+
+#[cfg(feature = "tim8")]
+ocref_clear!(crate::pac::TIM8,);
+
 macro_rules! pwm_all_channels {
     ($($TIMX:ident,)+) => {
         $(
@@ -180,6 +463,71 @@ macro_rules! pwm_all_channels {
                     //NOTE(unsafe) `PINS::Channels` is a ZST
                     unsafe { MaybeUninit::uninit().assume_init() }
                 }
+
+                /// Generates square waves of `freq` by toggling the channel outputs once per
+                /// half period
+                ///
+                /// The duty of a channel sets the phase of its wave, between 0 and the max duty.
+                /// Compare values and the auto-reload are preloaded, so a new duty takes effect
+                /// at the next update event without short pulses, or right away with `commit`.
+                ///
+                /// `freq` is valid up to half the timer clock. Higher frequencies saturate at
+                /// the fastest toggle rate, and 0 Hz gives the slowest one.
+                pub fn output_toggle<P, PINS, T>(mut self, _pins: PINS, freq: T) -> PINS::Channels
+                where
+                    PINS: Pins<crate::pac::$TIMX, P>,
+                    T: Into<Hertz>,
+                {
+                    if PINS::C1 {
+                        self.tim.ccmr1_output()
+                            .modify(|_, w| w.oc1pe().set_bit().oc1m().toggle() );
+                    }
+                    if PINS::C2 {
+                        self.tim.ccmr1_output()
+                            .modify(|_, w| w.oc2pe().set_bit().oc2m().toggle() );
+                    }
+                    if PINS::C3 {
+                        self.tim.ccmr2_output()
+                            .modify(|_, w| w.oc3pe().set_bit().oc3m().toggle() );
+                    }
+                    if PINS::C4 {
+                        self.tim.ccmr2_output()
+                            .modify(|_, w| w.oc4pe().set_bit().oc4m().toggle() );
+                    }
+
+                    // the output toggles once per counter period; frequencies above half the
+                    // timer clock saturate at the fastest period and 0 Hz at the slowest one
+                    let ticks = self
+                        .clk
+                        .0
+                        .checked_div(freq.into().0.saturating_mul(2))
+                        .unwrap_or(u32::MAX)
+                        .max(1);
+                    let psc = (ticks - 1) / (1 << 16);
+                    self.tim.set_prescaler(u16(psc).unwrap());
+                    let arr = ticks / (psc + 1);
+                    self.tim.set_auto_reload(arr).unwrap();
+
+                    // Trigger update event to load the registers
+                    self.tim.trigger_update();
+
+                    let _tim = &self.tim;
+                    brk!($TIMX, _tim);
+                    self.tim.cr1.write(|w|
+                        w.arpe()
+                            .set_bit()
+                            .cms()
+                            .bits(0b00)
+                            .dir()
+                            .clear_bit()
+                            .opm()
+                            .clear_bit()
+                            .cen()
+                            .set_bit()
+                    );
+                    //NOTE(unsafe) `PINS::Channels` is a ZST
+                    unsafe { MaybeUninit::uninit().assume_init() }
+                }
             }
 
             pwm_pin!(crate::pac::$TIMX, C1, ccr1, 0);
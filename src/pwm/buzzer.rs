@@ -0,0 +1,81 @@
+//! Tone and melody player for piezo buzzers
+//!
+//! ```ignore
+//! let channel = Timer::new(dp.TIM3, &clocks).pwm(buzzer_pin, 1.khz());
+//! let mut player = BuzzerPlayer::new(channel, delay, clocks);
+//! player.play(&[
+//!     Note::new(440.hz(), 200.ms()),
+//!     Note::rest(100.ms()),
+//!     Note::new(880.hz(), 400.ms()),
+//! ]);
+//! ```
+
+use super::SetFrequency;
+use crate::{
+    hal::{blocking::delay::DelayMs, PwmPin},
+    rcc::Clocks,
+    time::{Hertz, MilliSeconds},
+};
+
+/// A tone of a melody
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct Note {
+    /// Pitch of the tone, `0` for silence
+    pub freq: Hertz,
+    pub duration: MilliSeconds,
+}
+
+impl Note {
+    pub const fn new(freq: Hertz, duration: MilliSeconds) -> Self {
+        Self { freq, duration }
+    }
+
+    /// Silence for `duration`
+    pub const fn rest(duration: MilliSeconds) -> Self {
+        Self {
+            freq: Hertz(0),
+            duration,
+        }
+    }
+}
+
+/// Plays notes on a PWM channel by changing the frequency of its timer for every note
+///
+/// Tones are played with a 50% duty cycle, and rests with the output held low.
+pub struct BuzzerPlayer<PWM, D> {
+    pwm: PWM,
+    delay: D,
+    clocks: Clocks,
+}
+
+impl<PWM, D> BuzzerPlayer<PWM, D>
+where
+    PWM: PwmPin<Duty = u16> + SetFrequency,
+    D: DelayMs<u32>,
+{
+    pub fn new(mut pwm: PWM, delay: D, clocks: Clocks) -> Self {
+        pwm.set_duty(0);
+        pwm.enable();
+        Self { pwm, delay, clocks }
+    }
+
+    /// Plays `notes` one after the other, blocking until the last one has ended
+    pub fn play(&mut self, notes: &[Note]) {
+        for note in notes {
+            if note.freq.0 == 0 {
+                self.pwm.set_duty(0);
+            } else {
+                self.pwm.set_frequency(note.freq, &self.clocks);
+                self.pwm.set_duty(self.pwm.get_max_duty() / 2);
+            }
+            self.delay.delay_ms(note.duration.0);
+        }
+        self.pwm.set_duty(0);
+    }
+
+    /// Releases the PWM channel and the delay
+    pub fn release(mut self) -> (PWM, D) {
+        self.pwm.disable();
+        (self.pwm, self.delay)
+    }
+}
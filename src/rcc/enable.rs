@@ -9,6 +9,9 @@ macro_rules! bus_enable {
                 unsafe {
                     bb::set(Self::Bus::enr(rcc), $bit);
                 }
+                // Read back the enable register so the write has reached the bus bridge
+                // before the first access to the peripheral
+                let _ = Self::Bus::enr(rcc).read();
                 // Stall the pipeline to work around erratum 2.1.13 (DM00037591)
                 cortex_m::asm::dsb();
             }
@@ -29,8 +29,9 @@
 //!
 //! Whereas the hardware often supports flexible clock source selection and many clocks can be
 //! sourced from multiple PLLs, the code implements a fixed mapping between PLLs and clocks. The 48
-//! MHz clock is always generated by the main PLL, the I2S clocks are always generated by the I2S
-//! PLL (unless a matching external clock input is provided), and similarly the SAI clocks are
+//! MHz clock is generated by the main PLL (or by the SAI PLL on the STM32F446/469/479 if the main
+//! PLL can't produce it, see [`CFGR::require_48mhz`]), the I2S clocks are always generated by the
+//! I2S PLL (unless a matching external clock input is provided), and similarly the SAI clocks are
 //! always generated by the SAI PLL. It is therefore not possible to, for example, specify two
 //! different I2S frequencies unless you also provide a matching I2S_CKIN signal for one of them.
 //!
@@ -86,6 +87,17 @@ pub trait Reset: RccBus {
     fn reset(rcc: &RccRB);
 }
 
+/// Enables the clock of peripheral `P` outside of its HAL constructor
+///
+/// Returns only after the enable write has propagated, so the first access to
+/// the peripheral registers can't be lost.
+#[inline(always)]
+pub fn enable_and_sync<P: Enable>() {
+    // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+    let rcc = unsafe { &(*RCC::ptr()) };
+    P::enable(rcc);
+}
+
 /// Extension trait that constrains the `RCC` peripheral
 pub trait RccExt {
     /// Constrains the `RCC` peripheral so it plays nicely with the other abstractions
@@ -342,6 +354,7 @@ impl RccExt for RCC {
                 ))]
                 sai2_clk: None,
             },
+            calibration: Calibration { _0: () },
         }
     }
 }
@@ -349,6 +362,68 @@ impl RccExt for RCC {
 /// Constrained RCC peripheral
 pub struct Rcc {
     pub cfgr: CFGR,
+    pub calibration: Calibration,
+}
+
+/// Source of the MCO1 clock output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mco1 {
+    Hsi = 0b00,
+    Lse = 0b01,
+    Hse = 0b10,
+    Pll = 0b11,
+}
+
+/// Division factor of a clock output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum McoPre {
+    Div1 = 0b000,
+    Div2 = 0b100,
+    Div3 = 0b101,
+    Div4 = 0b110,
+    Div5 = 0b111,
+}
+
+/// Trimming of the HSI oscillator against a reference, with the MCO1 output to observe it
+///
+/// It stays usable once the clocks are frozen, so the trim can be adjusted while the HSI is
+/// measured, e.g. against an external reference clock or the LSE.
+pub struct Calibration {
+    _0: (),
+}
+
+impl Calibration {
+    /// Sets the user trim of the HSI (`HSITRIM`), `16` being the factory default
+    ///
+    /// Each step adjusts the frequency by roughly 40 kHz. Values above `31` are clamped.
+    #[allow(unused_unsafe)]
+    pub fn set_hsi_trim(&mut self, trim: u8) {
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.cr
+            .modify(|_, w| unsafe { w.hsitrim().bits(trim.min(0b1_1111)) });
+    }
+
+    /// Returns the user trim of the HSI
+    pub fn hsi_trim(&self) -> u8 {
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.cr.read().hsitrim().bits()
+    }
+
+    /// Returns the factory calibration of the HSI (`HSICAL`)
+    pub fn hsi_cal(&self) -> u8 {
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.cr.read().hsical().bits()
+    }
+
+    /// Outputs `source` divided by `prescaler` on MCO1
+    ///
+    /// The pin, `PA8`, has to be configured as alternate function 0.
+    #[allow(unused_unsafe)]
+    pub fn mco1(&mut self, source: Mco1, prescaler: McoPre) {
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.cfgr
+            .modify(|_, w| unsafe { w.mco1().bits(source as u8).mco1pre().bits(prescaler as u8) });
+    }
 }
 
 /// Built-in high speed clock frequency
@@ -567,6 +642,18 @@ impl CFGR {
         self
     }
 
+    /// Requires a valid 48 MHz clock for USB OTG FS, SDIO and RNG.
+    ///
+    /// The clock is taken from the Q output of the main PLL. On the STM32F446/469/479 the P
+    /// output of the SAI PLL is used instead if the main PLL can't produce 48 MHz for the
+    /// requested `sysclk` and the SAI PLL isn't generating a SAI clock. [`try_freeze`]
+    /// returns [`ClockError::Pll48clk`] if neither works, `freeze` panics.
+    ///
+    /// [`try_freeze`]: CFGR::try_freeze
+    pub fn require_48mhz(self) -> Self {
+        self.require_pll48clk()
+    }
+
     /// Declares that the selected frequency is available at the I2S clock input pin (I2S_CKIN).
     ///
     /// If this frequency matches the requested SAI or I2S frequencies, the external I2S clock is
@@ -784,6 +871,10 @@ impl CFGR {
         let i2s_pll = I2sPll::setup(pllsrcclk, i2s_clocks.pll_i2s_clk);
         #[cfg(feature = "stm32f446")]
         let sai_pll = SaiPll::setup(pllsrcclk, sai_clocks.pll_sai_clk);
+        #[cfg(feature = "stm32f446")]
+        let (sai_pll, pll48clk) = self.pll48clk_source(pllsrcclk, None, main_pll.pll48clk, sai_pll);
+        #[cfg(not(feature = "stm32f446"))]
+        let pll48clk = main_pll.pll48clk;
 
         PllSetup {
             use_pll: main_pll.use_pll,
@@ -791,7 +882,7 @@ impl CFGR {
             #[cfg(feature = "stm32f446")]
             use_saipll: sai_pll.use_pll,
             pllsysclk: main_pll.pllsysclk,
-            pll48clk: main_pll.pll48clk,
+            pll48clk,
             i2s: i2s_clocks.real(i2s_pll.plli2sclk, self.i2s_ckin),
             #[cfg(feature = "stm32f446")]
             sai: sai_clocks.real(sai_pll.sai_clk, self.i2s_ckin),
@@ -837,6 +928,11 @@ impl CFGR {
         ))]
         let sai_pll =
             SaiPll::setup_shared_m(pllsrcclk, main_pll.m.or(i2s_pll.m), sai_clocks.pll_sai_clk);
+        #[cfg(any(feature = "stm32f469", feature = "stm32f479"))]
+        let (sai_pll, pll48clk) =
+            self.pll48clk_source(pllsrcclk, main_pll.m, main_pll.pll48clk, sai_pll);
+        #[cfg(not(any(feature = "stm32f469", feature = "stm32f479")))]
+        let pll48clk = main_pll.pll48clk;
 
         PllSetup {
             use_pll: main_pll.use_pll,
@@ -851,7 +947,7 @@ impl CFGR {
             ))]
             use_saipll: sai_pll.use_pll,
             pllsysclk: main_pll.pllsysclk,
-            pll48clk: main_pll.pll48clk,
+            pll48clk,
             i2s: i2s_clocks.real(i2s_pll.plli2sclk, self.i2s_ckin),
             #[cfg(any(
                 feature = "stm32f427",
@@ -865,6 +961,36 @@ impl CFGR {
         }
     }
 
+    /// Selects the source of the 48 MHz clock, returning the SAI PLL and the 48 MHz frequency
+    ///
+    /// The Q output of the main PLL is kept unless it is out of the USB tolerance. The SAI PLL
+    /// is only used as a fallback while it doesn't generate a SAI clock.
+    #[cfg(any(feature = "stm32f446", feature = "stm32f469", feature = "stm32f479"))]
+    fn pll48clk_source(
+        &self,
+        pllsrcclk: u32,
+        m: Option<u32>,
+        main_pll48clk: Option<u32>,
+        sai_pll: SaiPll,
+    ) -> (SaiPll, Option<u32>) {
+        let (sai_pll, pll48clk, from_sai_pll) =
+            if !self.pll48clk || pll48clk_valid(main_pll48clk) || sai_pll.use_pll {
+                (sai_pll, main_pll48clk, false)
+            } else if let Some((sai_pll, pll48clk)) = SaiPll::setup_pll48clk(pllsrcclk, m) {
+                (sai_pll, Some(pll48clk), true)
+            } else {
+                (sai_pll, main_pll48clk, false)
+            };
+
+        let rcc = unsafe { &*RCC::ptr() };
+        #[cfg(feature = "stm32f446")]
+        rcc.dckcfgr2.modify(|_, w| w.ck48msel().bit(from_sai_pll));
+        #[cfg(not(feature = "stm32f446"))]
+        rcc.dckcfgr.modify(|_, w| w.ck48msel().bit(from_sai_pll));
+
+        (sai_pll, pll48clk)
+    }
+
     #[cfg(any(
         feature = "stm32f413",
         feature = "stm32f423",
@@ -980,8 +1106,16 @@ impl CFGR {
     }
 
     /// Initialises the hardware according to CFGR state returning a Clocks instance.
-    /// Panics if overclocking is attempted.
+    /// Panics if overclocking is attempted or the required 48 MHz clock can't be generated.
     pub fn freeze(self) -> Clocks {
+        self.try_freeze().expect("invalid clock configuration")
+    }
+
+    /// Initialises the hardware according to CFGR state returning a Clocks instance.
+    ///
+    /// Unlike `freeze`, an invalid configuration is reported as an error. The checks run before
+    /// any clock is switched, so the clock tree is left untouched in that case.
+    pub fn try_freeze(self) -> Result<Clocks, ClockError> {
         self.freeze_internal(false)
     }
 
@@ -994,9 +1128,10 @@ impl CFGR {
     /// recommended.
     pub unsafe fn freeze_unchecked(self) -> Clocks {
         self.freeze_internal(true)
+            .expect("invalid clock configuration")
     }
 
-    fn freeze_internal(self, unchecked: bool) -> Clocks {
+    fn freeze_internal(self, unchecked: bool) -> Result<Clocks, ClockError> {
         let rcc = unsafe { &*RCC::ptr() };
 
         //let (use_pll, sysclk_on_pll, sysclk, pll48clk) = self.pll_setup();
@@ -1011,7 +1146,12 @@ impl CFGR {
             sysclk
         };
 
-        assert!(unchecked || !sysclk_on_pll || (SYSCLK_MIN..=SYSCLK_MAX).contains(&sysclk));
+        if !unchecked && sysclk_on_pll && !(SYSCLK_MIN..=SYSCLK_MAX).contains(&sysclk) {
+            return Err(ClockError::Sysclk);
+        }
+        if self.pll48clk && !pll48clk_valid(plls.pll48clk) {
+            return Err(ClockError::Pll48clk);
+        }
 
         let hclk = self.hclk.unwrap_or(sysclk);
         let (hpre_bits, hpre_div) = match (sysclk + hclk - 1) / hclk {
@@ -1045,7 +1185,9 @@ impl CFGR {
         // Calculate real APB1 clock
         let pclk1 = hclk / u32::from(ppre1);
 
-        assert!(unchecked || pclk1 <= PCLK1_MAX);
+        if !unchecked && pclk1 > PCLK1_MAX {
+            return Err(ClockError::Pclk1);
+        }
 
         let pclk2 = self
             .pclk2
@@ -1062,7 +1204,9 @@ impl CFGR {
         // Calculate real APB2 clock
         let pclk2 = hclk / u32::from(ppre2);
 
-        assert!(unchecked || pclk2 <= PCLK2_MAX);
+        if !unchecked && pclk2 > PCLK2_MAX {
+            return Err(ClockError::Pclk2);
+        }
 
         Self::flash_setup(sysclk);
 
@@ -1234,14 +1378,30 @@ impl CFGR {
             sai2_clk: plls.sai.sai2_clk.map(Hertz),
         };
 
-        if self.pll48clk {
-            assert!(clocks.is_pll48clk_valid());
-        }
-
-        clocks
+        Ok(clocks)
     }
 }
 
+/// Invalid clock configuration, returned by [`CFGR::try_freeze`]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ClockError {
+    /// The system clock is outside of `SYSCLK_MIN..=SYSCLK_MAX`
+    Sysclk,
+    /// The APB1 clock is above `PCLK1_MAX`
+    Pclk1,
+    /// The APB2 clock is above `PCLK2_MAX`
+    Pclk2,
+    /// No PLL can generate the required 48 MHz clock within the USB tolerance
+    Pll48clk,
+}
+
+/// Returns true if the PLL48 clock is within the +-0.25% allowed by the USB specification
+fn pll48clk_valid(pll48clk: Option<u32>) -> bool {
+    pll48clk
+        .map(|freq| (48_000_000 - freq as i32).abs() <= 120_000)
+        .unwrap_or(false)
+}
+
 struct PllSetup {
     use_pll: bool,
     #[cfg(not(feature = "stm32f410"))]
@@ -1618,10 +1778,15 @@ impl Clocks {
     /// Returns true if the PLL48 clock is within USB
     /// specifications. It is required to use the USB functionality.
     pub fn is_pll48clk_valid(&self) -> bool {
-        // USB specification allows +-0.25%
-        self.pll48clk
-            .map(|freq| (48_000_000 - freq.0 as i32).abs() <= 120_000)
-            .unwrap_or(false)
+        pll48clk_valid(self.pll48clk.map(|freq| freq.0))
+    }
+
+    /// Returns the 48 MHz clock used by USB OTG FS, SDIO and RNG, if it
+    /// is within USB specifications.
+    ///
+    /// This is the output selected by [`CFGR::require_48mhz`], the main PLL or the SAI PLL.
+    pub fn usb_clk(&self) -> Option<Hertz> {
+        self.pll48clk.filter(|_| self.is_pll48clk_valid())
     }
 
     /// Returns the frequency of the I2S clock.
@@ -414,6 +414,66 @@ impl SaiPll {
         pll
     }
 
+    /// Configures the "P" output for the 48 MHz clock, returning the real frequency
+    ///
+    /// `m` is the divisor shared with the main PLL, if any. Returns `None` if no configuration
+    /// is within the USB tolerance. The SAI clock is not available from this PLL at the same
+    /// time.
+    #[cfg(any(feature = "stm32f446", feature = "stm32f469", feature = "stm32f479"))]
+    pub fn setup_pll48clk(pllsrcclk: u32, m: Option<u32>) -> Option<(SaiPll, u32)> {
+        // Input divisor from PLL source clock, must result to frequency in
+        // the range from 1 to 2 MHz
+        let pllm_min = m.unwrap_or((pllsrcclk + 1_999_999) / 2_000_000);
+        let pllm_max = m.unwrap_or(pllsrcclk / 1_000_000);
+
+        let (m, n, p, output, error) = (pllm_min..=pllm_max)
+            .flat_map(|m| [2, 4, 6, 8].iter().map(move |&p| (m, p)))
+            .filter_map(|(m, p)| {
+                let vco_in = pllsrcclk / m;
+                let n = (48_000_000 * p + (vco_in >> 1)) / vco_in;
+                let vco_out = vco_in * n;
+                if !(100_000_000..=432_000_000).contains(&vco_out) {
+                    return None;
+                }
+                let output = vco_out / p;
+                let error = (output as i32 - 48_000_000).abs() as u32;
+                Some((m, n, p, output, error))
+            })
+            .min_by_key(|(_, _, _, _, error)| *error)?;
+
+        // The USB specification allows +-0.25%
+        if error > 120_000 {
+            return None;
+        }
+
+        let rcc = unsafe { &*RCC::ptr() };
+        #[cfg(feature = "stm32f446")]
+        rcc.pllsaicfgr.modify(|_, w| unsafe {
+            w.pllsaim()
+                .bits(m as u8)
+                .pllsain()
+                .bits(n as u16)
+                .pllsaip()
+                .bits((p / 2 - 1) as u8)
+        });
+        #[cfg(not(feature = "stm32f446"))]
+        {
+            // "M" may have been written before, but the value is identical.
+            rcc.pllcfgr.modify(|_, w| unsafe { w.pllm().bits(m as u8) });
+            rcc.pllsaicfgr.modify(|_, w| unsafe {
+                w.pllsain().bits(n as u16).pllsaip().bits((p / 2 - 1) as u8)
+            });
+        }
+
+        Some((
+            SaiPll {
+                use_pll: true,
+                sai_clk: None,
+            },
+            output,
+        ))
+    }
+
     fn optimize_fixed_m(
         pllsrcclk: u32,
         m: u32,
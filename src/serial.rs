@@ -15,6 +15,11 @@
 //! the embedded-hal read and write traits with `u16` as the word type. You can use these
 //! implementations for 9-bit words.
 //!
+//! # Pin swapping
+//!
+//! The USARTs of the STM32F4 family have no TX/RX swap (`SWAP`) bit, unlike those of newer
+//! families. Crossed TX and RX lines can only be fixed by choosing other pins.
+//!
 
 use core::fmt;
 use core::marker::PhantomData;
@@ -47,6 +52,7 @@ use crate::pac::UART9;
 
 use crate::gpio::NoPin;
 use crate::rcc::Clocks;
+use crate::time::{Bps, Hertz};
 
 use crate::dma::traits::PeriAddress;
 
@@ -74,9 +80,19 @@ pub enum Event {
     Idle,
 }
 
+/// Receiver wakeup method used to leave mute mode
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum WakeUp {
+    /// Wake up when an idle frame is detected
+    IdleLine,
+    /// Wake up when a byte with the MSB set and a matching node address is received
+    AddressMark,
+}
+
 pub mod config {
     use crate::time::Bps;
     use crate::time::U32Ext;
+    pub use embedded_hal::spi::{Phase, Polarity};
 
     pub enum WordLength {
         DataBits8,
@@ -100,6 +116,30 @@ pub mod config {
         STOP1P5,
     }
 
+    /// Oversampling of the receiver
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum OverSampling {
+        /// 8 samples per bit: baud rates up to pclk / 8, with a lower noise margin
+        By8,
+        /// 16 samples per bit: baud rates up to pclk / 16
+        By16,
+    }
+
+    /// Clock output on the `CK` pin in synchronous mode
+    ///
+    /// The `CK` pin has to be set to its alternate function separately. UART4 and higher have
+    /// no `CK` pin and ignore this configuration.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct SyncClock {
+        pub polarity: Polarity,
+        pub phase: Phase,
+        /// Emit a clock pulse for the last data bit (`LBCL`)
+        ///
+        /// Devices that shift data in on each clock edge, like 74HC595 shift registers, need
+        /// it to latch all bits of the frame.
+        pub last_bit_clock: bool,
+    }
+
     pub enum DmaConfig {
         None,
         Tx,
@@ -113,6 +153,10 @@ pub mod config {
         pub parity: Parity,
         pub stopbits: StopBits,
         pub dma: DmaConfig,
+        /// Oversampling to use, or `None` to use 16x if the baud rate allows it and 8x otherwise
+        pub oversampling: Option<OverSampling>,
+        /// Clock output for synchronous mode, or `None` for asynchronous mode
+        pub synchronous: Option<SyncClock>,
     }
 
     impl Config {
@@ -150,6 +194,16 @@ pub mod config {
             self.stopbits = stopbits;
             self
         }
+
+        pub fn over_sampling(mut self, oversampling: OverSampling) -> Self {
+            self.oversampling = Some(oversampling);
+            self
+        }
+
+        pub fn synchronous(mut self, clock: SyncClock) -> Self {
+            self.synchronous = Some(clock);
+            self
+        }
     }
 
     #[derive(Debug)]
@@ -164,6 +218,8 @@ pub mod config {
                 parity: Parity::ParityNone,
                 stopbits: StopBits::STOP1,
                 dma: DmaConfig::None,
+                oversampling: None,
+                synchronous: None,
             }
         }
     }
@@ -211,6 +267,8 @@ pub type NoRx = NoPin;
 pub struct Serial<USART, PINS, WORD = u8> {
     usart: USART,
     pins: PINS,
+    baudrate: Bps,
+    oversampling: Option<config::OverSampling>,
     tx: Tx<USART, WORD>,
     rx: Rx<USART, WORD>,
 }
@@ -281,6 +339,35 @@ where
             let _ = (*USART::ptr()).dr.read();
         }
     }
+
+    /// Configures how the receiver leaves mute mode
+    pub fn set_wakeup(&mut self, wakeup: WakeUp) {
+        unsafe {
+            (*USART::ptr())
+                .cr1
+                .modify(|_, w| w.wake().bit(wakeup == WakeUp::AddressMark))
+        }
+    }
+
+    /// Sets the 4-bit node address used by the `WakeUp::AddressMark` wakeup method
+    pub fn set_address(&mut self, address: u8) {
+        assert!(address < 16);
+        unsafe { (*USART::ptr()).cr2.modify(|_, w| w.add().bits(address)) }
+    }
+
+    /// Mutes the receiver until the configured wakeup condition occurs
+    ///
+    /// While muted, no receive flags are set and no interrupts are generated.
+    /// The hardware un-mutes the receiver on wakeup; with `WakeUp::AddressMark`
+    /// the matching address byte is then received as a normal `Rxne` event.
+    pub fn enter_mute_mode(&mut self) {
+        unsafe { (*USART::ptr()).cr1.modify(|_, w| w.rwu().set_bit()) }
+    }
+
+    /// Return true if the receiver is still in mute mode
+    pub fn is_muted(&self) -> bool {
+        unsafe { (*USART::ptr()).cr1.read().rwu().bit_is_set() }
+    }
 }
 
 impl<USART, WORD> Tx<USART, WORD>
@@ -311,6 +398,13 @@ where
     pub fn is_tx_empty(&self) -> bool {
         unsafe { (*USART::ptr()).sr.read().txe().bit_is_set() }
     }
+
+    /// Transmits a break character after the current frame
+    ///
+    /// The hardware clears the request once the break has been sent.
+    pub fn send_break(&mut self) {
+        unsafe { (*USART::ptr()).cr1.modify(|_, w| w.sbk().set_bit()) }
+    }
 }
 
 impl<USART, PINS, WORD> AsRef<Tx<USART, WORD>> for Serial<USART, PINS, WORD> {
@@ -337,6 +431,70 @@ impl<USART, PINS, WORD> AsMut<Rx<USART, WORD>> for Serial<USART, PINS, WORD> {
     }
 }
 
+/// Returns the OVER8 bit and the BRR value for `baudrate`
+fn baud_rate_divider(
+    pclk: Hertz,
+    baudrate: Bps,
+    oversampling: Option<config::OverSampling>,
+) -> Result<(bool, u32), config::InvalidConfig> {
+    let pclk_freq = pclk.0;
+    let baud = baudrate.0;
+
+    // The frequency to calculate USARTDIV is this:
+    //
+    // (Taken from STM32F411xC/E Reference Manual,
+    // Section 19.3.4, Equation 1)
+    //
+    // 16 bit oversample: OVER8 = 0
+    // 8 bit oversample:  OVER8 = 1
+    //
+    // USARTDIV =          (pclk)
+    //            ------------------------
+    //            8 x (2 - OVER8) x (baud)
+    //
+    // BUT, the USARTDIV has 4 "fractional" bits, which effectively
+    // means that we need to "correct" the equation as follows:
+    //
+    // USARTDIV =      (pclk) * 16
+    //            ------------------------
+    //            8 x (2 - OVER8) x (baud)
+    //
+    // When OVER8 is enabled, we can only use the lowest three
+    // fractional bits, so we'll need to shift those last four bits
+    // right one bit
+
+    // Calculate correct baudrate divisor on the fly
+    let over8 = match oversampling {
+        Some(config::OverSampling::By8) => true,
+        Some(config::OverSampling::By16) => false,
+        None => (pclk_freq / 16) < baud,
+    };
+    if !over8 && (pclk_freq / 16) >= baud {
+        // We have the ability to oversample to 16 bits, take
+        // advantage of it.
+        //
+        // We also add `baud / 2` to the `pclk_freq` to ensure
+        // rounding of values to the closest scale, rather than the
+        // floored behavior of normal integer division.
+        let div = (pclk_freq + (baud / 2)) / baud;
+        Ok((false, div))
+    } else if over8 && (pclk_freq / 8) >= baud {
+        // We are close enough to pclk where we can only
+        // oversample 8, or 8x oversampling was requested.
+        //
+        // See note above regarding `baud` and rounding.
+        let div = ((pclk_freq * 2) + (baud / 2)) / baud;
+
+        // Ensure the the fractional bits (only 3) are
+        // right-aligned.
+        let frac = div & 0xF;
+        let div = (div & !0xF) | (frac >> 1);
+        Ok((true, div))
+    } else {
+        Err(config::InvalidConfig)
+    }
+}
+
 impl<USART, PINS, WORD> Serial<USART, PINS, WORD>
 where
     PINS: Pins<USART>,
@@ -365,57 +523,8 @@ where
             USART::reset(rcc);
         }
 
-        let pclk_freq = USART::clock(clocks).0;
-        let baud = config.baudrate.0;
-
-        // The frequency to calculate USARTDIV is this:
-        //
-        // (Taken from STM32F411xC/E Reference Manual,
-        // Section 19.3.4, Equation 1)
-        //
-        // 16 bit oversample: OVER8 = 0
-        // 8 bit oversample:  OVER8 = 1
-        //
-        // USARTDIV =          (pclk)
-        //            ------------------------
-        //            8 x (2 - OVER8) x (baud)
-        //
-        // BUT, the USARTDIV has 4 "fractional" bits, which effectively
-        // means that we need to "correct" the equation as follows:
-        //
-        // USARTDIV =      (pclk) * 16
-        //            ------------------------
-        //            8 x (2 - OVER8) x (baud)
-        //
-        // When OVER8 is enabled, we can only use the lowest three
-        // fractional bits, so we'll need to shift those last four bits
-        // right one bit
-
-        // Calculate correct baudrate divisor on the fly
-        let (over8, div) = if (pclk_freq / 16) >= baud {
-            // We have the ability to oversample to 16 bits, take
-            // advantage of it.
-            //
-            // We also add `baud / 2` to the `pclk_freq` to ensure
-            // rounding of values to the closest scale, rather than the
-            // floored behavior of normal integer division.
-            let div = (pclk_freq + (baud / 2)) / baud;
-            (false, div)
-        } else if (pclk_freq / 8) >= baud {
-            // We are close enough to pclk where we can only
-            // oversample 8.
-            //
-            // See note above regarding `baud` and rounding.
-            let div = ((pclk_freq * 2) + (baud / 2)) / baud;
-
-            // Ensure the the fractional bits (only 3) are
-            // right-aligned.
-            let frac = div & 0xF;
-            let div = (div & !0xF) | (frac >> 1);
-            (true, div)
-        } else {
-            return Err(config::InvalidConfig);
-        };
+        let (over8, div) =
+            baud_rate_divider(USART::clock(clocks), config.baudrate, config.oversampling)?;
 
         unsafe { (*USART::ptr()).brr.write(|w| w.bits(div)) };
 
@@ -423,6 +532,28 @@ where
         unsafe { (*USART::ptr()).cr2.reset() };
         unsafe { (*USART::ptr()).cr3.reset() };
 
+        // The clock bits have to be written while the transmitter is disabled. They are set as
+        // raw bits because the register block of the UARTs, which is used on some devices,
+        // lacks them.
+        if let Some(clock) = config.synchronous {
+            const LBCL: u32 = 1 << 8;
+            const CPHA: u32 = 1 << 9;
+            const CPOL: u32 = 1 << 10;
+            const CLKEN: u32 = 1 << 11;
+
+            let mut bits = CLKEN;
+            if clock.polarity == Polarity::IdleHigh {
+                bits |= CPOL;
+            }
+            if clock.phase == Phase::CaptureOnSecondTransition {
+                bits |= CPHA;
+            }
+            if clock.last_bit_clock {
+                bits |= LBCL;
+            }
+            unsafe { (*USART::ptr()).cr2.modify(|r, w| w.bits(r.bits() | bits)) };
+        }
+
         // Enable transmission and receiving
         // and configure frame
         unsafe {
@@ -463,6 +594,8 @@ where
         Ok(Serial {
             usart,
             pins,
+            baudrate: config.baudrate,
+            oversampling: config.oversampling,
             tx: Tx::new(),
             rx: Rx::new(),
         }
@@ -473,6 +606,25 @@ where
 
         (self.usart, self.pins)
     }
+
+    /// Recomputes the baud rate divider for new `clocks`, keeping the configured baud rate
+    ///
+    /// Call this after the system clock was changed at runtime. It waits for the ongoing
+    /// transmission to complete. If the baud rate can't be reached with the new clocks, the
+    /// divider is left unchanged.
+    pub fn reconfigure(&mut self, clocks: &Clocks) -> Result<(), config::InvalidConfig> {
+        let (over8, div) =
+            baud_rate_divider(USART::clock(clocks), self.baudrate, self.oversampling)?;
+
+        // NOTE(unsafe) atomic read with no side effects, and writes to registers owned by `self`
+        let usart = unsafe { &*USART::ptr() };
+        while usart.sr.read().tc().bit_is_clear() {}
+        usart.cr1.modify(|_, w| w.ue().clear_bit());
+        usart.brr.write(|w| unsafe { w.bits(div) });
+        usart.cr1.modify(|_, w| w.over8().bit(over8).ue().set_bit());
+
+        Ok(())
+    }
 }
 
 impl<USART, TX, WORD, const TXA: u8> Serial<USART, (TX, NoPin), WORD>
@@ -553,6 +705,37 @@ where
         }
     }
 
+    /// Configures how the receiver leaves mute mode
+    pub fn set_wakeup(&mut self, wakeup: WakeUp) {
+        self.rx.set_wakeup(wakeup)
+    }
+
+    /// Sets the 4-bit node address used by the `WakeUp::AddressMark` wakeup method
+    pub fn set_address(&mut self, address: u8) {
+        self.rx.set_address(address)
+    }
+
+    /// Mutes the receiver until the configured wakeup condition occurs
+    ///
+    /// See [`Rx::enter_mute_mode`] for details.
+    pub fn enter_mute_mode(&mut self) {
+        self.rx.enter_mute_mode()
+    }
+
+    /// Return true if the receiver is still in mute mode
+    pub fn is_muted(&self) -> bool {
+        self.rx.is_muted()
+    }
+
+    /// Samples each bit once instead of three times with a majority vote (`ONEBIT`)
+    ///
+    /// A single sample no longer flags noise errors and tolerates less noise, but it tolerates
+    /// a larger clock deviation, which allows higher baud rates on clean links. Together with
+    /// `OverSampling::By8` this gives the highest reachable baud rate.
+    pub fn one_bit_sampling(&mut self, enable: bool) {
+        unsafe { (*USART::ptr()).cr3.modify(|_, w| w.onebit().bit(enable)) }
+    }
+
     pub fn split(self) -> (Tx<USART, WORD>, Rx<USART, WORD>) {
         (self.tx, self.rx)
     }
@@ -569,6 +752,8 @@ where
         Serial {
             usart: self.usart,
             pins: self.pins,
+            baudrate: self.baudrate,
+            oversampling: self.oversampling,
             tx: Tx::new(),
             rx: Rx::new(),
         }
@@ -586,6 +771,8 @@ where
         Serial {
             usart: self.usart,
             pins: self.pins,
+            baudrate: self.baudrate,
+            oversampling: self.oversampling,
             tx: Tx::new(),
             rx: Rx::new(),
         }
@@ -899,7 +1086,7 @@ macro_rules! halUsart {
                 use crate::pac::usart1::cr2::STOP_A;
                 use config::StopBits;
 
-                self.cr2.write(|w| {
+                self.cr2.modify(|_, w| {
                     w.stop().variant(match bits {
                         StopBits::STOP0P5 => STOP_A::STOP0P5,
                         StopBits::STOP1 => STOP_A::STOP1,
@@ -933,7 +1120,7 @@ macro_rules! halUart {
                 use crate::pac::uart4::cr2::STOP_A;
                 use config::StopBits;
 
-                self.cr2.write(|w| {
+                self.cr2.modify(|_, w| {
                     w.stop().variant(match bits {
                         StopBits::STOP0P5 => STOP_A::STOP1,
                         StopBits::STOP1 => STOP_A::STOP1,
@@ -1,9 +1,14 @@
+use core::convert::Infallible;
 use core::marker::PhantomData;
 use core::ops::Deref;
 use core::ptr;
 
-use crate::dma::traits::PeriAddress;
+use crate::dma::config::DmaConfig;
+use crate::dma::traits::{Channel, DMASet, PeriAddress, Stream};
+use crate::dma::{ChannelX, MemoryToPeripheral, PeripheralToMemory, Transfer};
 use crate::gpio::{Const, NoPin, PinA, PushPull, SetAlternate};
+use embedded_dma::{StaticReadBuffer, StaticWriteBuffer};
+use embedded_hal::digital::v2::OutputPin;
 use embedded_hal::spi;
 pub use embedded_hal::spi::{Mode, Phase, Polarity};
 
@@ -35,6 +40,10 @@ pub enum Error {
     ModeFault,
     /// CRC error
     Crc,
+    /// A frame didn't complete within the allowed number of polls
+    Timeout,
+    /// The requested bus frequency is zero
+    InvalidFrequency,
 }
 
 pub struct Sck;
@@ -70,6 +79,37 @@ where
     }
 }
 
+impl<
+        SPI,
+        SCK,
+        MISO,
+        MOSI,
+        NSS,
+        const SCKA: u8,
+        const MISOA: u8,
+        const MOSIA: u8,
+        const NSSA: u8,
+    > Pins<SPI> for (SCK, MISO, MOSI, NSS)
+where
+    SCK: PinA<Sck, SPI, A = Const<SCKA>> + SetAlternate<PushPull, SCKA>,
+    MISO: PinA<Miso, SPI, A = Const<MISOA>> + SetAlternate<PushPull, MISOA>,
+    MOSI: PinA<Mosi, SPI, A = Const<MOSIA>> + SetAlternate<PushPull, MOSIA>,
+    NSS: PinA<Nss, SPI, A = Const<NSSA>> + SetAlternate<PushPull, NSSA>,
+{
+    fn set_alt_mode(&mut self) {
+        self.0.set_alt_mode();
+        self.1.set_alt_mode();
+        self.2.set_alt_mode();
+        self.3.set_alt_mode();
+    }
+    fn restore_mode(&mut self) {
+        self.0.restore_mode();
+        self.1.restore_mode();
+        self.2.restore_mode();
+        self.3.restore_mode();
+    }
+}
+
 /// A filler type for when the SCK pin is unnecessary
 pub type NoSck = NoPin;
 /// A filler type for when the Miso pin is unnecessary
@@ -87,16 +127,71 @@ pub enum Event {
     Error,
 }
 
+/// DMA requests of the SPI
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DmaRequest {
+    /// Transmit buffer empty (`TXDMAEN`)
+    Tx,
+    /// Receive buffer not empty (`RXDMAEN`)
+    Rx,
+}
+
+/// Order in which the bits of a frame are shifted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitOrder {
+    MsbFirst,
+    LsbFirst,
+}
+
+/// Frame format of the bus
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// Motorola format, with the clock polarity and phase of the mode
+    Motorola,
+    /// TI synchronous serial format, where NSS pulses before each frame
+    Ti,
+}
+
+/// Bus settings required by one slave device
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpiDeviceConfig {
+    pub mode: Mode,
+    pub freq: Hertz,
+    pub bit_order: BitOrder,
+}
+
+/// Size of the SPI frames, `u8` or `u16`
+pub trait FrameSize: Copy + crate::Sealed {
+    /// Value of the `DFF` bit for this size
+    const DFF: bool;
+}
+
+impl crate::Sealed for u8 {}
+impl FrameSize for u8 {
+    const DFF: bool = false;
+}
+
+impl crate::Sealed for u16 {}
+impl FrameSize for u16 {
+    const DFF: bool = true;
+}
+
 /// Normal mode - RX and TX pins are independent
 pub struct TransferModeNormal;
 /// BIDI mode - use TX pin as RX then spi receive data
 pub struct TransferModeBidi;
 
 #[derive(Debug)]
-pub struct Spi<SPI, PINS, TRANSFER_MODE> {
+pub struct Spi<SPI, PINS, TRANSFER_MODE, WORD = u8> {
     spi: SPI,
     pins: PINS,
     transfer_mode: TRANSFER_MODE,
+    _word: PhantomData<WORD>,
+    expected_mode: Option<Mode>,
+    freq: Hertz,
+    clock: Hertz,
+    #[cfg(feature = "spi-fault-injection")]
+    injected_error: Option<Error>,
 }
 
 // Implemented by all SPI instances
@@ -158,6 +253,12 @@ where
             spi,
             pins,
             transfer_mode: TransferModeNormal,
+            _word: PhantomData,
+            expected_mode: None,
+            freq: Hertz(0),
+            clock: Hertz(0),
+            #[cfg(feature = "spi-fault-injection")]
+            injected_error: None,
         }
         .pre_init(mode, freq.into(), SPI::clock(clocks))
         .init()
@@ -170,6 +271,35 @@ where
     }
 }
 
+impl<SPI, SCK, MISO, MOSI, NSS> Spi<SPI, (SCK, MISO, MOSI, NSS), TransferModeNormal>
+where
+    SPI: Instance,
+    (SCK, MISO, MOSI, NSS): Pins<SPI>,
+{
+    /// Creates a master whose NSS pin is driven by the peripheral (`SSM` clear, `SSOE` set)
+    ///
+    /// NSS goes low as soon as the bus is enabled and stays low until it is disabled, so it
+    /// suits a single slave that tolerates a chip select held low between transfers.
+    ///
+    /// As NSS is an output, it is not monitored: a mode fault can't occur, and the bus can't
+    /// detect another master. Multi-master setups need NSS as input instead (`SSOE` clear),
+    /// where another master pulling it low raises a mode fault that clears `MSTR` and `SPE`.
+    pub fn new_with_hw_nss(
+        spi: SPI,
+        pins: (SCK, MISO, MOSI, NSS),
+        mode: Mode,
+        freq: impl Into<Hertz>,
+        clocks: &Clocks,
+    ) -> Self {
+        let mut spi = Self::new(spi, pins, mode, freq, clocks);
+        spi.enable(false);
+        spi.spi.cr2.modify(|_, w| w.ssoe().set_bit());
+        spi.spi.cr1.modify(|_, w| w.ssm().clear_bit());
+        spi.enable(true);
+        spi
+    }
+}
+
 impl<SPI, PINS> Spi<SPI, PINS, TransferModeBidi>
 where
     SPI: Instance,
@@ -195,6 +325,12 @@ where
             spi,
             pins,
             transfer_mode: TransferModeBidi,
+            _word: PhantomData,
+            expected_mode: None,
+            freq: Hertz(0),
+            clock: Hertz(0),
+            #[cfg(feature = "spi-fault-injection")]
+            injected_error: None,
         }
         .pre_init(mode, freq.into(), SPI::clock(clocks))
         .init()
@@ -207,7 +343,32 @@ where
     }
 }
 
-impl<SPI, PINS, TRANSFER_MODE> Spi<SPI, PINS, TRANSFER_MODE>
+impl<SPI, PINS, WORD> Spi<SPI, PINS, TransferModeBidi, WORD>
+where
+    SPI: Instance,
+{
+    /// Switches the direction of the data line.
+    ///
+    /// Changing BIDIOE while the bus is enabled can produce an extra clock edge,
+    /// so the sequence is: wait for the last frame to leave the shift register,
+    /// clear SPE, change BIDIOE, set SPE again.
+    fn set_bidi_output(&mut self, output: bool) {
+        if self.spi.cr1.read().bidioe().bit() == output {
+            return;
+        }
+
+        if !output {
+            while self.spi.sr.read().txe().bit_is_clear() {}
+            while self.spi.sr.read().bsy().bit_is_set() {}
+        }
+
+        self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+        self.spi.cr1.modify(|_, w| w.bidioe().bit(output));
+        self.spi.cr1.modify(|_, w| w.spe().set_bit());
+    }
+}
+
+impl<SPI, PINS, TRANSFER_MODE, WORD> Spi<SPI, PINS, TRANSFER_MODE, WORD>
 where
     SPI: Instance,
     PINS: Pins<SPI>,
@@ -237,6 +398,251 @@ where
 
         self
     }
+
+    /// Writes `words` without reading back a byte after each one.
+    ///
+    /// The transmit register is refilled as soon as it is empty, which keeps the bus busy
+    /// back-to-back, unlike the blocking `Write` implementation. The received data is
+    /// discarded once the last frame has been shifted out, which also clears the resulting
+    /// overrun.
+    pub fn write_fast(&mut self, words: &[u8]) -> Result<(), Error> {
+        self.debug_check_mode();
+        for word in words {
+            loop {
+                let sr = self.spi.sr.read();
+                if sr.modf().bit_is_set() {
+                    // Write to CR1 to clear MODF
+                    self.spi.cr1.modify(|_r, w| w);
+                    return Err(Error::ModeFault);
+                } else if sr.txe().bit_is_set() {
+                    break;
+                }
+            }
+            self.send_u8(*word);
+        }
+
+        while self.spi.sr.read().txe().bit_is_clear() {}
+        while self.spi.sr.read().bsy().bit_is_set() {}
+        self.drain_rx();
+
+        Ok(())
+    }
+
+    /// Writes `cmd`, then reads `resp.len()` bytes by clocking out `0x00` bytes.
+    ///
+    /// The bytes received while `cmd` is sent are discarded and the receive register is drained
+    /// before the read starts, so the first byte of `resp` is the first byte of the response.
+    pub fn write_then_read(&mut self, cmd: &[u8], resp: &mut [u8]) -> Result<(), Error> {
+        self.write_fast(cmd)?;
+        for byte in resp.iter_mut() {
+            nb::block!(self.check_send(0))?;
+            *byte = nb::block!(self.check_read())?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `words` and stores the bytes received meanwhile into `rx`.
+    ///
+    /// Unlike `transfer`, `words` is left untouched. Received bytes beyond the length of `rx` are
+    /// discarded.
+    pub fn write_capturing(&mut self, words: &[u8], rx: &mut [u8]) -> Result<(), Error> {
+        for (i, word) in words.iter().enumerate() {
+            nb::block!(self.check_send(*word))?;
+            let byte = nb::block!(self.check_read())?;
+            if let Some(slot) = rx.get_mut(i) {
+                *slot = byte;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes `words` and returns the byte received during the last one, or `0` if `words` is
+    /// empty.
+    ///
+    /// Suits devices returning a status while the end of a command is sent.
+    pub fn write_capture_last(&mut self, words: &[u8]) -> Result<u8, Error> {
+        let mut last = 0;
+        for word in words {
+            nb::block!(self.check_send(*word))?;
+            last = nb::block!(self.check_read())?;
+        }
+
+        Ok(last)
+    }
+
+    /// Starts receiving in receive-only master mode (`RXONLY`).
+    ///
+    /// The clock runs continuously from here on, so every byte has to be read with `read` in
+    /// time to avoid an overrun. Use [`stop_receive_only`](Self::stop_receive_only) to stop.
+    pub fn start_receive_only(&mut self) {
+        self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+        self.spi.cr1.modify(|_, w| w.rxonly().set_bit());
+        self.spi.cr1.modify(|_, w| w.spe().set_bit());
+    }
+
+    /// Stops a receive-only transfer and returns the last byte.
+    ///
+    /// Call this right after the second to last byte was read. Following the sequence of the
+    /// reference manual, the bus is disabled one SCK period later, which lets the last byte
+    /// complete, and that byte is then read once RXNE is set.
+    pub fn stop_receive_only(&mut self) -> u8 {
+        self.delay_sck_period();
+        self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+
+        while self.spi.sr.read().rxne().bit_is_clear() {}
+        let byte = self.read_u8();
+
+        // Back to full-duplex, which only clocks when data is written
+        self.spi.cr1.modify(|_, w| w.rxonly().clear_bit());
+        self.spi.cr1.modify(|_, w| w.spe().set_bit());
+        byte
+    }
+}
+
+impl<SPI, PINS, WORD> Spi<SPI, PINS, TransferModeNormal, WORD>
+where
+    SPI: Instance,
+    WORD: FrameSize,
+{
+    /// Transfers `words` followed by their CRC, and checks the CRC received after them
+    ///
+    /// The CRC calculation has to be enabled with [`enable_crc`](Self::enable_crc). It is
+    /// reset first, so the CRC covers `words` only. Returns `Error::Crc` if the received CRC
+    /// doesn't match, with `words` holding the received data.
+    pub fn transfer_with_crc(&mut self, words: &mut [WORD]) -> Result<(), Error> {
+        if words.is_empty() {
+            return Ok(());
+        }
+        self.reset_crc();
+        let last = words.len() - 1;
+        for (i, word) in words.iter_mut().enumerate() {
+            nb::block!(self.check_send(*word))?;
+            if i == last {
+                self.send_crc_next();
+            }
+            *word = nb::block!(self.check_read())?;
+        }
+        self.check_crc()
+    }
+
+    /// Writes `words` followed by their CRC, and checks the CRC received after them
+    ///
+    /// Like [`transfer_with_crc`](Self::transfer_with_crc), with the received data discarded.
+    pub fn write_with_crc(&mut self, words: &[WORD]) -> Result<(), Error> {
+        if words.is_empty() {
+            return Ok(());
+        }
+        self.reset_crc();
+        let last = words.len() - 1;
+        for (i, word) in words.iter().enumerate() {
+            nb::block!(self.check_send(*word))?;
+            if i == last {
+                self.send_crc_next();
+            }
+            nb::block!(self.check_read())?;
+        }
+        self.check_crc()
+    }
+
+    /// Transfers `words` in place, keeping the next frame queued while one is received
+    ///
+    /// The blocking `Transfer` waits for each frame to be received before it sends the next
+    /// one, which leaves the bus idle between frames. Here the next frame is written to the
+    /// transmit buffer as soon as the current one moves to the shift register, so frames
+    /// follow each other back-to-back and each received frame is read while the next one is
+    /// shifted. A frame that isn't read within one frame time, e.g. because an interrupt
+    /// handler runs in between, is overwritten by the next one and reported as
+    /// `Error::Overrun` rather than lost silently.
+    pub fn transfer_in_place(&mut self, words: &mut [WORD]) -> Result<(), Error> {
+        if words.is_empty() {
+            return Ok(());
+        }
+        nb::block!(self.check_send(words[0]))?;
+        for i in 0..words.len() {
+            if let Some(&next) = words.get(i + 1) {
+                nb::block!(self.check_send(next))?;
+            }
+            words[i] = nb::block!(self.check_read())?;
+        }
+
+        Ok(())
+    }
+
+    /// Transfers `words` like the blocking `Transfer`, but gives up with `Error::Timeout`
+    ///
+    /// Every frame may poll the status register up to `timeout` times to be sent, and as many
+    /// times to be received, so a bus that stopped clocking, like a slave whose master is
+    /// disconnected, can't hang the caller.
+    pub fn transfer_with_timeout(&mut self, words: &mut [WORD], timeout: u32) -> Result<(), Error> {
+        for word in words.iter_mut() {
+            let send = *word;
+            poll_with_limit(timeout, || self.check_send(send))?;
+            *word = poll_with_limit(timeout, || self.check_read())?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `words` like the blocking `Write`, but gives up with `Error::Timeout`
+    ///
+    /// See [`transfer_with_timeout`](Self::transfer_with_timeout).
+    pub fn write_with_timeout(&mut self, words: &[WORD], timeout: u32) -> Result<(), Error> {
+        for word in words {
+            poll_with_limit(timeout, || self.check_send(*word))?;
+            poll_with_limit(timeout, || self.check_read())?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts writing `buf` over DMA on `stream`, and returns a handle to poll it
+    ///
+    /// The bus stays enabled throughout. The bytes received meanwhile are discarded once the
+    /// transfer is waited for.
+    pub fn write_dma<STREAM, BUF, const CHANNEL: u8>(
+        self,
+        stream: STREAM,
+        buf: BUF,
+    ) -> SpiDmaTransfer<SPI, PINS, STREAM, BUF, WORD, CHANNEL>
+    where
+        STREAM: Stream,
+        ChannelX<CHANNEL>: Channel,
+        Tx<SPI, WORD>: DMASet<STREAM, MemoryToPeripheral, CHANNEL>,
+        BUF: StaticReadBuffer<Word = WORD>,
+    {
+        let tx = Tx {
+            spi: PhantomData,
+            _word: PhantomData,
+        };
+        let mut transfer =
+            Transfer::init_memory_to_peripheral(stream, tx, buf, None, DmaConfig::default());
+        transfer.start(|_| {});
+        // the request is raised right away, so the stream has to be enabled first
+        self.spi.cr2.modify(|_, w| w.txdmaen().enabled());
+
+        SpiDmaTransfer {
+            spi: self,
+            transfer,
+        }
+    }
+
+    /// Reads the CRC frame that follows the data and reports a mismatch
+    fn check_crc(&mut self) -> Result<(), Error> {
+        while self.spi.sr.read().rxne().bit_is_clear() {}
+        let _crc = self.read_word();
+        if self.spi.sr.read().crcerr().bit_is_set() {
+            // Clear the CRCERR bit
+            self.spi.sr.modify(|_r, w| {
+                w.crcerr().clear_bit();
+                w
+            });
+            Err(Error::Crc)
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl<SPI, PINS> Spi<SPI, PINS, TransferModeBidi>
@@ -259,19 +665,113 @@ where
     }
 }
 
-impl<SPI, PINS, TRANSFER_MODE> Spi<SPI, PINS, TRANSFER_MODE>
+impl<SPI, PINS, WORD> Spi<SPI, PINS, TransferModeBidi, WORD>
 where
     SPI: Instance,
+    WORD: FrameSize,
+{
+    /// Fills `words` with the data line as input, without writing dummy frames
+    ///
+    /// The clock runs continuously while the line is an input, so it is stopped following
+    /// the sequence of the reference manual: one SCK period after the second to last frame
+    /// the bus is disabled, which lets the last frame complete. The line is an output again
+    /// afterwards, which keeps the clock idle.
+    pub fn read_into(&mut self, words: &mut [WORD]) -> Result<(), Error> {
+        let (last, head) = match words.split_last_mut() {
+            Some(split) => split,
+            None => return Ok(()),
+        };
+
+        self.set_bidi_output(false);
+        let mut result = Ok(());
+        for word in head.iter_mut() {
+            match nb::block!(self.check_read()) {
+                Ok(value) => *word = value,
+                Err(error) => {
+                    result = Err(error);
+                    break;
+                }
+            }
+        }
+
+        self.delay_sck_period();
+        self.enable(false);
+        if result.is_ok() {
+            while self.spi.sr.read().rxne().bit_is_clear() {}
+            *last = self.read_word();
+        }
+        self.spi.cr1.modify(|_, w| w.bidioe().set_bit());
+        self.enable(true);
+        result
+    }
+}
+
+impl<SPI, PINS, TRANSFER_MODE, WORD> Spi<SPI, PINS, TRANSFER_MODE, WORD>
+where
+    SPI: Instance,
+    WORD: FrameSize,
 {
     /// Convert the spi to another transfer mode.
     fn into_mode<TRANSFER_MODE2>(
         self,
         transfer_mode: TRANSFER_MODE2,
-    ) -> Spi<SPI, PINS, TRANSFER_MODE2> {
+    ) -> Spi<SPI, PINS, TRANSFER_MODE2, WORD> {
         Spi {
             spi: self.spi,
             pins: self.pins,
             transfer_mode,
+            _word: PhantomData,
+            expected_mode: self.expected_mode,
+            freq: self.freq,
+            clock: self.clock,
+            #[cfg(feature = "spi-fault-injection")]
+            injected_error: self.injected_error,
+        }
+    }
+
+    /// Convert the spi to another frame size.
+    fn into_frame_size<WORD2: FrameSize>(self) -> Spi<SPI, PINS, TRANSFER_MODE, WORD2> {
+        while self.spi.sr.read().txe().bit_is_clear() {}
+        while self.spi.sr.read().bsy().bit_is_set() {}
+        self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+        self.spi.cr1.modify(|_, w| w.dff().bit(WORD2::DFF));
+        self.spi.cr1.modify(|_, w| w.spe().set_bit());
+
+        Spi {
+            spi: self.spi,
+            pins: self.pins,
+            transfer_mode: self.transfer_mode,
+            _word: PhantomData,
+            expected_mode: self.expected_mode,
+            freq: self.freq,
+            clock: self.clock,
+            #[cfg(feature = "spi-fault-injection")]
+            injected_error: self.injected_error,
+        }
+    }
+
+    /// Asserts in debug builds that the bus is still in `expected` mode before every write
+    ///
+    /// This catches a bus shared between devices being left in the mode of another device.
+    /// Release builds skip the check.
+    pub fn with_mode_check(mut self, expected: Mode) -> Self {
+        self.expected_mode = Some(expected);
+        self
+    }
+
+    #[inline(always)]
+    fn debug_check_mode(&self) {
+        if cfg!(debug_assertions) {
+            if let Some(expected) = self.expected_mode {
+                let cr1 = self.spi.cr1.read();
+                let cpol = cr1.cpol().bit_is_set();
+                let cpha = cr1.cpha().bit_is_set();
+                debug_assert!(
+                    cpol == (expected.polarity == Polarity::IdleHigh)
+                        && cpha == (expected.phase == Phase::CaptureOnSecondTransition),
+                    "SPI mode does not match the mode expected by the device"
+                );
+            }
         }
     }
 
@@ -284,21 +784,13 @@ where
     }
 
     /// Pre initializing the SPI bus.
-    pub fn pre_init(self, mode: Mode, freq: Hertz, clock: Hertz) -> Self {
+    pub fn pre_init(mut self, mode: Mode, freq: Hertz, clock: Hertz) -> Self {
         // disable SS output
         self.spi.cr2.write(|w| w.ssoe().clear_bit());
 
-        let br = match clock.0 / freq.0 {
-            0 => unreachable!(),
-            1..=2 => 0b000,
-            3..=5 => 0b001,
-            6..=11 => 0b010,
-            12..=23 => 0b011,
-            24..=47 => 0b100,
-            48..=95 => 0b101,
-            96..=191 => 0b110,
-            _ => 0b111,
-        };
+        self.freq = freq;
+        self.clock = clock;
+        let br = Self::baud_rate_bits(freq, clock);
 
         self.spi.cr1.write(|w| {
             w.cpha()
@@ -321,14 +813,230 @@ where
                 .set_bit()
                 .rxonly()
                 .clear_bit()
-                // dff: 8 bit frames
+                // dff: 8 or 16 bit frames
                 .dff()
-                .clear_bit()
+                .bit(WORD::DFF)
         });
 
         self
     }
 
+    /// Returns the baud rate control value (`BR`) for a rate close to `freq`
+    ///
+    /// Rates above half the bus clock give the fastest setting, a zero rate the slowest one.
+    fn baud_rate_bits(freq: Hertz, clock: Hertz) -> u8 {
+        match clock.0.checked_div(freq.0).unwrap_or(u32::MAX) {
+            0..=2 => 0b000,
+            3..=5 => 0b001,
+            6..=11 => 0b010,
+            12..=23 => 0b011,
+            24..=47 => 0b100,
+            48..=95 => 0b101,
+            96..=191 => 0b110,
+            _ => 0b111,
+        }
+    }
+
+    /// Waits at least one SCK period
+    fn delay_sck_period(&self) {
+        // One SCK period is 2^(BR + 1) APB clock cycles, which is at most 16 core clock cycles
+        // each with the largest APB prescaler.
+        let br = self.spi.cr1.read().br().bits();
+        cortex_m::asm::delay(16 << (br + 1));
+    }
+
+    /// Recomputes the baud rate prescaler for new `clocks`, keeping the requested frequency
+    ///
+    /// Call this after the system clock was changed at runtime. It waits for the ongoing
+    /// transfer to end.
+    pub fn reconfigure(&mut self, clocks: &Clocks) {
+        self.clock = SPI::clock(clocks);
+        let br = Self::baud_rate_bits(self.freq, self.clock);
+
+        // BSY may still be clear right after the last byte was written to DR
+        while self.spi.sr.read().txe().bit_is_clear() {}
+        while self.spi.sr.read().bsy().bit_is_set() {}
+        self.enable(false);
+        self.spi.cr1.modify(|_, w| w.br().bits(br));
+        self.enable(true);
+    }
+
+    /// Changes the bus frequency to a rate close to `freq`
+    ///
+    /// The ongoing transfer completes first. Mode, transfer mode and master configuration are
+    /// kept, so the pins don't have to be released to talk to a slave with another clock limit.
+    /// Rates above half the bus clock give the fastest rate. Fails with
+    /// `Error::InvalidFrequency`, keeping the current rate, if `freq` is zero.
+    pub fn set_frequency(&mut self, freq: impl Into<Hertz>, clocks: &Clocks) -> Result<(), Error> {
+        let freq = freq.into();
+        if freq.0 == 0 {
+            return Err(Error::InvalidFrequency);
+        }
+        self.freq = freq;
+        self.clock = SPI::clock(clocks);
+        let br = Self::baud_rate_bits(self.freq, self.clock);
+
+        while self.spi.sr.read().txe().bit_is_clear() {}
+        while self.spi.sr.read().bsy().bit_is_set() {}
+        self.enable(false);
+        self.spi.cr1.modify(|_, w| w.br().bits(br));
+        self.enable(true);
+        Ok(())
+    }
+
+    /// Returns the SCK frequency actually generated
+    ///
+    /// The bus clock is divided by a power of two from 2 to 256 (`BR`), so this is usually
+    /// not the frequency that was requested but one close to it, which may also be higher.
+    pub fn frequency(&self, clocks: &Clocks) -> Hertz {
+        let br = self.spi.cr1.read().br().bits();
+        Hertz(SPI::clock(clocks).0 >> (br + 1))
+    }
+
+    /// Selects the frame format (`FRF`), waiting for the ongoing transfer to end
+    ///
+    /// In TI mode the clock polarity and phase of the mode are ignored: data is sampled on
+    /// the falling edge, and the NSS pin pulses high for one clock before each frame. The NSS
+    /// pin has to be in its alternate function mode, for example with
+    /// [`new_with_hw_nss`](Spi::new_with_hw_nss).
+    pub fn set_frame_format(&mut self, format: FrameFormat) {
+        while self.spi.sr.read().txe().bit_is_clear() {}
+        while self.spi.sr.read().bsy().bit_is_set() {}
+        self.enable(false);
+        self.spi
+            .cr2
+            .modify(|_, w| w.frf().bit(format == FrameFormat::Ti));
+        self.enable(true);
+    }
+
+    /// Returns the current mode, frequency and bit order of the bus
+    pub fn config(&self) -> SpiDeviceConfig {
+        let cr1 = self.spi.cr1.read();
+        SpiDeviceConfig {
+            mode: Mode {
+                polarity: if cr1.cpol().bit_is_set() {
+                    Polarity::IdleHigh
+                } else {
+                    Polarity::IdleLow
+                },
+                phase: if cr1.cpha().bit_is_set() {
+                    Phase::CaptureOnSecondTransition
+                } else {
+                    Phase::CaptureOnFirstTransition
+                },
+            },
+            freq: self.freq,
+            bit_order: if cr1.lsbfirst().bit_is_set() {
+                BitOrder::LsbFirst
+            } else {
+                BitOrder::MsbFirst
+            },
+        }
+    }
+
+    /// Switches the bus to the mode, frequency and bit order of `config`
+    ///
+    /// The ongoing transfer completes first, as these settings can only change while the bus is
+    /// disabled. A [mode check](Self::with_mode_check) follows the new mode.
+    pub fn apply_config(&mut self, config: &SpiDeviceConfig) {
+        let br = Self::baud_rate_bits(config.freq, self.clock);
+
+        while self.spi.sr.read().txe().bit_is_clear() {}
+        while self.spi.sr.read().bsy().bit_is_set() {}
+        self.enable(false);
+        self.spi.cr1.modify(|_, w| {
+            w.cpha()
+                .bit(config.mode.phase == Phase::CaptureOnSecondTransition)
+                .cpol()
+                .bit(config.mode.polarity == Polarity::IdleHigh)
+                .br()
+                .bits(br)
+                .lsbfirst()
+                .bit(config.bit_order == BitOrder::LsbFirst)
+        });
+        self.enable(true);
+
+        self.freq = config.freq;
+        if self.expected_mode.is_some() {
+            self.expected_mode = Some(config.mode);
+        }
+    }
+
+    /// Runs `f` with the bus switched to `config`, then restores the previous settings
+    ///
+    /// This lets drivers of slaves with different requirements share one bus.
+    pub fn with_config<F, R>(&mut self, config: &SpiDeviceConfig, f: F) -> R
+    where
+        F: FnOnce(&mut Self) -> R,
+    {
+        let previous = self.config();
+        self.apply_config(config);
+        let result = f(self);
+        self.apply_config(&previous);
+        result
+    }
+
+    /// Enables the hardware CRC calculation with `polynomial` (`CRCPR`)
+    ///
+    /// The CRC has the size of the frames. The ongoing transfer completes first. See
+    /// [`transfer_with_crc`](Spi::transfer_with_crc) to append and check the CRC.
+    #[allow(unused_unsafe)]
+    pub fn enable_crc(&mut self, polynomial: u16) {
+        while self.spi.sr.read().txe().bit_is_clear() {}
+        while self.spi.sr.read().bsy().bit_is_set() {}
+        self.enable(false);
+        self.spi
+            .crcpr
+            .write(|w| unsafe { w.crcpoly().bits(polynomial) });
+        self.spi.cr1.modify(|_, w| w.crcen().set_bit());
+        self.enable(true);
+    }
+
+    /// Disables the hardware CRC calculation
+    pub fn disable_crc(&mut self) {
+        while self.spi.sr.read().txe().bit_is_clear() {}
+        while self.spi.sr.read().bsy().bit_is_set() {}
+        self.enable(false);
+        self.spi.cr1.modify(|_, w| w.crcen().clear_bit());
+        self.enable(true);
+    }
+
+    /// Clears the CRC of the previous frames, to start a new CRC protected transfer
+    pub fn reset_crc(&mut self) {
+        while self.spi.sr.read().txe().bit_is_clear() {}
+        while self.spi.sr.read().bsy().bit_is_set() {}
+        self.enable(false);
+        self.spi.cr1.modify(|_, w| w.crcen().clear_bit());
+        self.spi.cr1.modify(|_, w| w.crcen().set_bit());
+        self.enable(true);
+    }
+
+    /// Sends the transmit CRC after the frame in progress (`CRCNEXT`)
+    ///
+    /// It has to be called right after the last data frame was written.
+    pub fn send_crc_next(&mut self) {
+        self.spi.cr1.modify(|_, w| w.crcnext().set_bit());
+    }
+
+    /// Returns the CRC of the received frames (`RXCRCR`)
+    pub fn rx_crc(&self) -> u16 {
+        self.spi.rxcrcr.read().rx_crc().bits()
+    }
+
+    /// Returns the CRC of the transmitted frames (`TXCRCR`)
+    pub fn tx_crc(&self) -> u16 {
+        self.spi.txcrcr.read().tx_crc().bits()
+    }
+
+    /// Sets the internal NSS level used when software slave management is enabled (`SSI`).
+    ///
+    /// `Spi::new` sets it high, as a master needs. Driving it low while in master mode makes the
+    /// peripheral see itself deselected: this raises a mode fault (MODF), which clears MSTR and
+    /// SPE until the error is handled.
+    pub fn set_internal_nss(&mut self, value: bool) {
+        self.spi.cr1.modify(|_, w| w.ssi().bit(value));
+    }
+
     /// Enable interrupts for the given `event`:
     ///  - Received data ready to be read (RXNE)
     ///  - Transmit data register empty (TXE)
@@ -353,6 +1061,40 @@ where
         }
     }
 
+    /// Clears a mode fault and makes the bus a working master again
+    ///
+    /// On a mode fault the hardware clears `MSTR` and `SPE`. This reads SR and writes CR1,
+    /// which clears MODF, then sets `MSTR` and `SPE` again. The cause of the fault, a low NSS
+    /// input or [internal NSS](Self::set_internal_nss) level, has to be gone, or the fault is
+    /// raised again right away.
+    pub fn recover_from_mode_fault(&mut self) {
+        let _ = self.spi.sr.read();
+        self.spi.cr1.modify(|_, w| w.mstr().set_bit());
+        self.enable(true);
+    }
+
+    /// Enables the DMA `request`
+    ///
+    /// The reference manual orders the requests around the streams: enable `Rx` before its
+    /// stream, so that no received frame is missed, and `Tx` after its stream, as the request
+    /// is raised right away while the transmit buffer is empty. [`write_dma`](Self::write_dma)
+    /// follows the same order. To pause a stream, e.g. to swap ping-pong buffers in its
+    /// interrupt, disable the request before the stream, and enable it again after the stream.
+    pub fn listen_dma(&mut self, request: DmaRequest) {
+        match request {
+            DmaRequest::Tx => self.spi.cr2.modify(|_, w| w.txdmaen().enabled()),
+            DmaRequest::Rx => self.spi.cr2.modify(|_, w| w.rxdmaen().enabled()),
+        }
+    }
+
+    /// Disables the DMA `request`
+    pub fn unlisten_dma(&mut self, request: DmaRequest) {
+        match request {
+            DmaRequest::Tx => self.spi.cr2.modify(|_, w| w.txdmaen().disabled()),
+            DmaRequest::Rx => self.spi.cr2.modify(|_, w| w.rxdmaen().disabled()),
+        }
+    }
+
     /// Return `true` if the TXE flag is set, i.e. new data to transmit
     /// can be written to the SPI.
     pub fn is_txe(&self) -> bool {
@@ -377,48 +1119,115 @@ where
         self.spi.sr.read().ovr().bit_is_set()
     }
 
-    pub fn use_dma(self) -> DmaBuilder<SPI> {
-        DmaBuilder { spi: self.spi }
+    /// Discards any received data and clears the overrun flag.
+    ///
+    /// Reads DR until RXNE is clear, then SR, which is the sequence that clears OVR, so the next
+    /// transfer does not return a stale byte.
+    pub fn drain_rx(&mut self) {
+        while self.spi.sr.read().rxne().bit_is_set() {
+            let _ = self.read_word();
+        }
+        let _ = self.spi.sr.read();
+    }
+
+    /// Clears the overrun flag and returns the frame that was last received
+    ///
+    /// This is the sequence of the reference manual: read DR, then SR. The frames lost to the
+    /// overrun can't be recovered, and frames received since still have to be read, see
+    /// [`drain_rx`](Self::drain_rx) to discard them as well.
+    pub fn clear_overrun(&mut self) -> WORD {
+        let word = self.read_word();
+        let _ = self.spi.sr.read();
+        word
+    }
+
+    /// Runs `f` with the chip select `cs` asserted (driven low).
+    ///
+    /// Once `f` returns, or unwinds, the last frame is shifted out and `cs` is released again, so
+    /// an early return with `?` inside the closure can never leave the slave selected.
+    pub fn transaction<CS, F, R>(&mut self, cs: &mut CS, f: F) -> R
+    where
+        CS: OutputPin<Error = Infallible>,
+        F: FnOnce(&mut Self) -> R,
+    {
+        let _guard = CsGuard::<SPI, CS>::new(cs);
+        f(self)
+    }
+
+    /// Makes the next read or write report `error` as if the status register had flagged it
+    ///
+    /// The usual recovery for that error (draining RX, clearing MODF or CRCERR) still runs, so
+    /// error paths of drivers and of the HAL itself can be exercised without a faulty bus.
+    /// Errors without a status flag, like `Error::Timeout`, are returned as they are.
+    #[cfg(feature = "spi-fault-injection")]
+    pub fn inject_error(&mut self, error: Error) {
+        self.injected_error = Some(error);
+    }
+
+    #[inline(always)]
+    fn take_injected_error(&mut self) -> Option<Error> {
+        #[cfg(feature = "spi-fault-injection")]
+        {
+            self.injected_error.take()
+        }
+        #[cfg(not(feature = "spi-fault-injection"))]
+        {
+            None
+        }
+    }
+
+    pub fn use_dma(self) -> DmaBuilder<SPI, WORD> {
+        DmaBuilder {
+            spi: self.spi,
+            _word: PhantomData,
+        }
     }
 
     #[inline(always)]
-    fn check_read(&mut self) -> nb::Result<u8, Error> {
+    fn check_read(&mut self) -> nb::Result<WORD, Error> {
+        let fault = self.take_injected_error();
         let sr = self.spi.sr.read();
 
-        Err(if sr.ovr().bit_is_set() {
+        Err(if sr.ovr().bit_is_set() || fault == Some(Error::Overrun) {
+            self.drain_rx();
             Error::Overrun.into()
-        } else if sr.modf().bit_is_set() {
+        } else if sr.modf().bit_is_set() || fault == Some(Error::ModeFault) {
             Error::ModeFault.into()
-        } else if sr.crcerr().bit_is_set() {
+        } else if sr.crcerr().bit_is_set() || fault == Some(Error::Crc) {
             Error::Crc.into()
+        } else if let Some(error) = fault {
+            error.into()
         } else if sr.rxne().bit_is_set() {
-            return Ok(self.read_u8());
+            return Ok(self.read_word());
         } else {
             nb::Error::WouldBlock
         })
     }
 
     #[inline(always)]
-    fn check_send(&mut self, byte: u8) -> nb::Result<(), Error> {
+    fn check_send(&mut self, word: WORD) -> nb::Result<(), Error> {
+        self.debug_check_mode();
+        let fault = self.take_injected_error();
         let sr = self.spi.sr.read();
 
-        Err(if sr.ovr().bit_is_set() {
-            // Read from the DR to clear the OVR bit
-            let _ = self.spi.dr.read();
+        Err(if sr.ovr().bit_is_set() || fault == Some(Error::Overrun) {
+            self.drain_rx();
             Error::Overrun.into()
-        } else if sr.modf().bit_is_set() {
+        } else if sr.modf().bit_is_set() || fault == Some(Error::ModeFault) {
             // Write to CR1 to clear MODF
             self.spi.cr1.modify(|_r, w| w);
             Error::ModeFault.into()
-        } else if sr.crcerr().bit_is_set() {
+        } else if sr.crcerr().bit_is_set() || fault == Some(Error::Crc) {
             // Clear the CRCERR bit
             self.spi.sr.modify(|_r, w| {
                 w.crcerr().clear_bit();
                 w
             });
             Error::Crc.into()
+        } else if let Some(error) = fault {
+            error.into()
         } else if sr.txe().bit_is_set() {
-            self.send_u8(byte);
+            self.send_word(word);
             return Ok(());
         } else {
             nb::Error::WouldBlock
@@ -436,116 +1245,378 @@ where
         // NOTE(write_volatile) see note above
         unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut u8, byte) }
     }
+
+    #[inline(always)]
+    fn read_word(&mut self) -> WORD {
+        // NOTE(read_volatile) read one frame, 1 byte or a half-word
+        unsafe { ptr::read_volatile(&self.spi.dr as *const _ as *const WORD) }
+    }
+
+    #[inline(always)]
+    fn send_word(&mut self, word: WORD) {
+        // NOTE(write_volatile) see note above
+        unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut WORD, word) }
+    }
 }
 
-pub struct DmaBuilder<SPI> {
+impl<SPI, PINS, TRANSFER_MODE> Spi<SPI, PINS, TRANSFER_MODE, u8>
+where
+    SPI: Instance,
+{
+    /// Switches to 16-bit frames (`DFF`), waiting for the ongoing transfer to end
+    ///
+    /// Reads and writes then operate on `u16`, and so do the DMA halves of `use_dma`.
+    pub fn with_u16_data(self) -> Spi<SPI, PINS, TRANSFER_MODE, u16> {
+        self.into_frame_size()
+    }
+}
+
+impl<SPI, PINS, TRANSFER_MODE> Spi<SPI, PINS, TRANSFER_MODE, u16>
+where
+    SPI: Instance,
+{
+    /// Switches back to 8-bit frames, waiting for the ongoing transfer to end
+    pub fn with_u8_data(self) -> Spi<SPI, PINS, TRANSFER_MODE, u8> {
+        self.into_frame_size()
+    }
+}
+
+/// Calls `f` until it completes, at most `limit` times
+fn poll_with_limit<T>(limit: u32, mut f: impl FnMut() -> nb::Result<T, Error>) -> Result<T, Error> {
+    for _ in 0..limit {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(nb::Error::Other(error)) => return Err(error),
+            Err(nb::Error::WouldBlock) => {}
+        }
+    }
+    Err(Error::Timeout)
+}
+
+/// Releases the chip select of a [`Spi::transaction`] once the bus is idle
+struct CsGuard<'a, SPI: Instance, CS: OutputPin<Error = Infallible>> {
+    cs: &'a mut CS,
+    _spi: PhantomData<SPI>,
+}
+
+impl<'a, SPI: Instance, CS: OutputPin<Error = Infallible>> CsGuard<'a, SPI, CS> {
+    fn new(cs: &'a mut CS) -> Self {
+        let _ = cs.set_low();
+        Self {
+            cs,
+            _spi: PhantomData,
+        }
+    }
+}
+
+impl<SPI: Instance, CS: OutputPin<Error = Infallible>> Drop for CsGuard<'_, SPI, CS> {
+    fn drop(&mut self) {
+        // NOTE(unsafe) read only access to the status register
+        let spi = unsafe { &*SPI::ptr() };
+        while spi.sr.read().txe().bit_is_clear() {}
+        while spi.sr.read().bsy().bit_is_set() {}
+        let _ = self.cs.set_high();
+    }
+}
+
+pub struct DmaBuilder<SPI, WORD = u8> {
     spi: SPI,
+    _word: PhantomData<WORD>,
 }
 
-pub struct Tx<SPI> {
+/// DMA transmit half of a SPI
+///
+/// It can only be used in a `Transfer` on the DMA streams and channels connected to the
+/// `SPIx_TX` request, see [`DMASet`](crate::dma::traits::DMASet).
+pub struct Tx<SPI, WORD = u8> {
     spi: PhantomData<SPI>,
+    _word: PhantomData<WORD>,
 }
 
-pub struct Rx<SPI> {
+/// DMA receive half of a SPI
+///
+/// It can only be used in a `Transfer` on the DMA streams and channels connected to the
+/// `SPIx_RX` request, see [`DMASet`](crate::dma::traits::DMASet).
+pub struct Rx<SPI, WORD = u8> {
     spi: PhantomData<SPI>,
+    _word: PhantomData<WORD>,
 }
 
-impl<SPI> DmaBuilder<SPI>
+impl<SPI, WORD> DmaBuilder<SPI, WORD>
 where
     SPI: Instance,
 {
-    pub fn tx(self) -> Tx<SPI> {
+    pub fn tx(self) -> Tx<SPI, WORD> {
         self.new_tx()
     }
 
-    pub fn rx(self) -> Rx<SPI> {
+    pub fn rx(self) -> Rx<SPI, WORD> {
         self.new_rx()
     }
 
-    pub fn txrx(self) -> (Tx<SPI>, Rx<SPI>) {
+    pub fn txrx(self) -> (Tx<SPI, WORD>, Rx<SPI, WORD>) {
         (self.new_tx(), self.new_rx())
     }
 
-    fn new_tx(&self) -> Tx<SPI> {
+    fn new_tx(&self) -> Tx<SPI, WORD> {
         self.spi.cr2.modify(|_, w| w.txdmaen().enabled());
-        Tx { spi: PhantomData }
+        Tx {
+            spi: PhantomData,
+            _word: PhantomData,
+        }
     }
 
-    fn new_rx(self) -> Rx<SPI> {
+    fn new_rx(self) -> Rx<SPI, WORD> {
         self.spi.cr2.modify(|_, w| w.rxdmaen().enabled());
-        Rx { spi: PhantomData }
+        Rx {
+            spi: PhantomData,
+            _word: PhantomData,
+        }
     }
 }
 
-unsafe impl<SPI> PeriAddress for Rx<SPI>
+unsafe impl<SPI, WORD> PeriAddress for Rx<SPI, WORD>
 where
     SPI: Instance,
+    WORD: FrameSize,
 {
     #[inline(always)]
     fn address(&self) -> u32 {
         unsafe { &(*SPI::ptr()).dr as *const _ as u32 }
     }
 
-    type MemSize = u8;
+    type MemSize = WORD;
 }
 
-unsafe impl<SPI> PeriAddress for Tx<SPI>
+unsafe impl<SPI, WORD> PeriAddress for Tx<SPI, WORD>
 where
     SPI: Instance,
+    WORD: FrameSize,
 {
     #[inline(always)]
     fn address(&self) -> u32 {
         unsafe { &(*SPI::ptr()).dr as *const _ as u32 }
     }
 
-    type MemSize = u8;
+    type MemSize = WORD;
+}
+
+// The DMA requests don't depend on the frame size, so 16-bit halves use the streams and
+// channels of the 8-bit ones
+unsafe impl<SPI, STREAM, DIR, const CHANNEL: u8> DMASet<STREAM, DIR, CHANNEL> for Tx<SPI, u16> where
+    Tx<SPI, u8>: DMASet<STREAM, DIR, CHANNEL>
+{
+}
+unsafe impl<SPI, STREAM, DIR, const CHANNEL: u8> DMASet<STREAM, DIR, CHANNEL> for Rx<SPI, u16> where
+    Rx<SPI, u8>: DMASet<STREAM, DIR, CHANNEL>
+{
+}
+
+impl<SPI: Instance, WORD: FrameSize> Tx<SPI, WORD> {
+    /// Starts writing `buf` over DMA with `cs` held low for the whole transfer
+    ///
+    /// `cs` is only set high again by [`CsTransfer::wait`], once the last byte has left the
+    /// shift register.
+    pub fn transaction<STREAM, BUF, CS, const CHANNEL: u8>(
+        self,
+        stream: STREAM,
+        buf: BUF,
+        mut cs: CS,
+        config: DmaConfig,
+    ) -> CsTransfer<STREAM, SPI, BUF, CS, WORD, CHANNEL>
+    where
+        STREAM: Stream,
+        ChannelX<CHANNEL>: Channel,
+        Self: DMASet<STREAM, MemoryToPeripheral, CHANNEL>,
+        BUF: StaticReadBuffer<Word = WORD>,
+        CS: OutputPin<Error = Infallible>,
+    {
+        let mut transfer = Transfer::init_memory_to_peripheral(stream, self, buf, None, config);
+        cs.set_low().ok();
+        transfer.start(|_| {});
+        CsTransfer { transfer, cs }
+    }
+}
+
+impl<SPI: Instance, WORD: FrameSize> Rx<SPI, WORD> {
+    /// Starts receiving into `first` and `second` in turn, using DMA double buffering
+    ///
+    /// The stream fills one buffer while the other one is processed. At each transfer
+    /// complete, the hardware toggles `CT` to continue with the other buffer, `M0AR` (`first`)
+    /// or `M1AR` (`second`), and `STREAM::current_buffer()` tells which one is being filled.
+    /// The transfer complete interrupt is the point to swap: there,
+    /// [`next_transfer_with`](Transfer::next_transfer_with) hands over the buffer just filled
+    /// and takes the one to fill next.
+    ///
+    /// A master only clocks while it transmits, so continuous reception needs slave mode or a
+    /// transmit stream running alongside.
+    pub fn receive_double_buffered<STREAM, BUF, const CHANNEL: u8>(
+        self,
+        stream: STREAM,
+        first: BUF,
+        second: BUF,
+        config: DmaConfig,
+    ) -> Transfer<STREAM, Self, PeripheralToMemory, BUF, CHANNEL>
+    where
+        STREAM: Stream,
+        ChannelX<CHANNEL>: Channel,
+        Self: DMASet<STREAM, PeripheralToMemory, CHANNEL>,
+        BUF: StaticWriteBuffer<Word = WORD>,
+    {
+        let mut transfer = Transfer::init_peripheral_to_memory(
+            stream,
+            self,
+            first,
+            Some(second),
+            config.double_buffer(true),
+        );
+        transfer.start(|_| {});
+        transfer
+    }
 }
 
-impl<SPI, PINS> spi::FullDuplex<u8> for Spi<SPI, PINS, TransferModeNormal>
+/// DMA write on a SPI framed by a chip select, see [`Tx::transaction`]
+pub struct CsTransfer<STREAM, SPI, BUF, CS, WORD, const CHANNEL: u8>
 where
     SPI: Instance,
+    STREAM: Stream,
+    WORD: FrameSize,
+{
+    transfer: Transfer<STREAM, Tx<SPI, WORD>, MemoryToPeripheral, BUF, CHANNEL>,
+    cs: CS,
+}
+
+impl<STREAM, SPI, BUF, CS, WORD, const CHANNEL: u8> CsTransfer<STREAM, SPI, BUF, CS, WORD, CHANNEL>
+where
+    SPI: Instance,
+    STREAM: Stream,
+    ChannelX<CHANNEL>: Channel,
+    Tx<SPI, WORD>: DMASet<STREAM, MemoryToPeripheral, CHANNEL>,
+    BUF: StaticReadBuffer<Word = WORD>,
+    WORD: FrameSize,
+    CS: OutputPin<Error = Infallible>,
+{
+    /// Returns `true` once the last byte has been shifted out
+    ///
+    /// The DMA transfer completes as soon as the last byte is written to the data register,
+    /// so the SPI is still sending when its transfer complete flag is set.
+    pub fn is_complete(&self) -> bool {
+        let sr = unsafe { (*SPI::ptr()).sr.read() };
+        STREAM::get_transfer_complete_flag() && sr.txe().bit_is_set() && sr.bsy().bit_is_clear()
+    }
+
+    /// Waits for the last byte to be shifted out, sets `cs` high and returns the resources
+    pub fn wait(mut self) -> (STREAM, Tx<SPI, WORD>, BUF, CS) {
+        while !self.transfer.full_complete() {}
+        let spi = unsafe { &*SPI::ptr() };
+        while spi.sr.read().txe().bit_is_clear() {}
+        while spi.sr.read().bsy().bit_is_set() {}
+        self.cs.set_high().ok();
+
+        let (stream, tx, buf, _) = self.transfer.release();
+        (stream, tx, buf, self.cs)
+    }
+}
+
+/// DMA write started by [`Spi::write_dma`]
+pub struct SpiDmaTransfer<SPI, PINS, STREAM, BUF, WORD, const CHANNEL: u8>
+where
+    SPI: Instance,
+    STREAM: Stream,
+    WORD: FrameSize,
+{
+    spi: Spi<SPI, PINS, TransferModeNormal, WORD>,
+    transfer: Transfer<STREAM, Tx<SPI, WORD>, MemoryToPeripheral, BUF, CHANNEL>,
+}
+
+impl<SPI, PINS, STREAM, BUF, WORD, const CHANNEL: u8>
+    SpiDmaTransfer<SPI, PINS, STREAM, BUF, WORD, CHANNEL>
+where
+    SPI: Instance,
+    STREAM: Stream,
+    ChannelX<CHANNEL>: Channel,
+    Tx<SPI, WORD>: DMASet<STREAM, MemoryToPeripheral, CHANNEL>,
+    BUF: StaticReadBuffer<Word = WORD>,
+    WORD: FrameSize,
+{
+    /// Returns `true` once the last frame has been shifted out
+    pub fn is_done(&self) -> bool {
+        let sr = self.spi.spi.sr.read();
+        STREAM::get_transfer_complete_flag() && sr.txe().bit_is_set() && sr.bsy().bit_is_clear()
+    }
+
+    /// Returns `true` and clears the half transfer flag once half of the buffer was written
+    pub fn is_half_done(&mut self) -> bool {
+        self.transfer.half_complete()
+    }
+
+    /// Waits for the last frame to be shifted out and returns the SPI, the stream and the buffer
+    ///
+    /// The flags of the stream are cleared, and the overrun caused by the unread received
+    /// frames as well.
+    pub fn wait(mut self) -> (Spi<SPI, PINS, TransferModeNormal, WORD>, STREAM, BUF) {
+        while !self.transfer.full_complete() {}
+        while self.spi.spi.sr.read().txe().bit_is_clear() {}
+        while self.spi.spi.sr.read().bsy().bit_is_set() {}
+
+        let (stream, _tx, buf, _) = self.transfer.release();
+        let mut spi = self.spi;
+        spi.spi.cr2.modify(|_, w| w.txdmaen().disabled());
+        spi.drain_rx();
+        (spi, stream, buf)
+    }
+}
+
+impl<SPI, PINS, WORD> spi::FullDuplex<WORD> for Spi<SPI, PINS, TransferModeNormal, WORD>
+where
+    SPI: Instance,
+    WORD: FrameSize,
 {
     type Error = Error;
 
-    fn read(&mut self) -> nb::Result<u8, Error> {
+    fn read(&mut self) -> nb::Result<WORD, Error> {
         self.check_read()
     }
 
-    fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
-        self.check_send(byte)
+    fn send(&mut self, word: WORD) -> nb::Result<(), Error> {
+        self.check_send(word)
     }
 }
 
-impl<SPI, PINS> spi::FullDuplex<u8> for Spi<SPI, PINS, TransferModeBidi>
+impl<SPI, PINS, WORD> spi::FullDuplex<WORD> for Spi<SPI, PINS, TransferModeBidi, WORD>
 where
     SPI: Instance,
+    WORD: FrameSize,
 {
     type Error = Error;
 
-    fn read(&mut self) -> nb::Result<u8, Error> {
-        self.spi.cr1.modify(|_, w| w.bidioe().clear_bit());
+    fn read(&mut self) -> nb::Result<WORD, Error> {
+        self.set_bidi_output(false);
         self.check_read()
     }
 
-    fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
-        self.spi.cr1.modify(|_, w| w.bidioe().set_bit());
-        self.check_send(byte)
+    fn send(&mut self, word: WORD) -> nb::Result<(), Error> {
+        self.set_bidi_output(true);
+        self.check_send(word)
     }
 }
 
+mod hal_1;
+
 mod blocking {
-    use super::{Error, Instance, Spi, TransferModeBidi, TransferModeNormal};
+    use super::{Error, FrameSize, Instance, Spi, TransferModeBidi, TransferModeNormal};
     use embedded_hal::blocking::spi::{Operation, Transactional, Transfer, Write, WriteIter};
     use embedded_hal::spi::FullDuplex;
 
-    impl<SPI, PINS, TRANSFER_MODE> Transfer<u8> for Spi<SPI, PINS, TRANSFER_MODE>
+    impl<SPI, PINS, TRANSFER_MODE, WORD> Transfer<WORD> for Spi<SPI, PINS, TRANSFER_MODE, WORD>
     where
-        Self: FullDuplex<u8, Error = Error>,
+        Self: FullDuplex<WORD, Error = Error>,
         SPI: Instance,
+        WORD: FrameSize,
     {
         type Error = Error;
 
-        fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+        fn transfer<'w>(&mut self, words: &'w mut [WORD]) -> Result<&'w [WORD], Self::Error> {
             for word in words.iter_mut() {
                 nb::block!(self.send(*word))?;
                 *word = nb::block!(self.read())?;
@@ -555,14 +1626,15 @@ mod blocking {
         }
     }
 
-    impl<SPI, PINS> Write<u8> for Spi<SPI, PINS, TransferModeNormal>
+    impl<SPI, PINS, WORD> Write<WORD> for Spi<SPI, PINS, TransferModeNormal, WORD>
     where
-        Self: FullDuplex<u8, Error = Error>,
+        Self: FullDuplex<WORD, Error = Error>,
         SPI: Instance,
+        WORD: FrameSize,
     {
         type Error = Error;
 
-        fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        fn write(&mut self, words: &[WORD]) -> Result<(), Self::Error> {
             for word in words {
                 nb::block!(self.send(*word))?;
                 nb::block!(self.read())?;
@@ -572,14 +1644,15 @@ mod blocking {
         }
     }
 
-    impl<SPI, PINS> Write<u8> for Spi<SPI, PINS, TransferModeBidi>
+    impl<SPI, PINS, WORD> Write<WORD> for Spi<SPI, PINS, TransferModeBidi, WORD>
     where
-        Self: FullDuplex<u8, Error = Error>,
+        Self: FullDuplex<WORD, Error = Error>,
         SPI: Instance,
+        WORD: FrameSize,
     {
         type Error = Error;
 
-        fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        fn write(&mut self, words: &[WORD]) -> Result<(), Self::Error> {
             for word in words {
                 nb::block!(self.send(*word))?;
             }
@@ -588,16 +1661,17 @@ mod blocking {
         }
     }
 
-    impl<SPI, PINS> WriteIter<u8> for Spi<SPI, PINS, TransferModeNormal>
+    impl<SPI, PINS, WORD> WriteIter<WORD> for Spi<SPI, PINS, TransferModeNormal, WORD>
     where
-        Self: FullDuplex<u8, Error = Error>,
+        Self: FullDuplex<WORD, Error = Error>,
         SPI: Instance,
+        WORD: FrameSize,
     {
         type Error = Error;
 
         fn write_iter<WI>(&mut self, words: WI) -> Result<(), Self::Error>
         where
-            WI: IntoIterator<Item = u8>,
+            WI: IntoIterator<Item = WORD>,
         {
             for word in words.into_iter() {
                 nb::block!(self.send(word))?;
@@ -608,16 +1682,17 @@ mod blocking {
         }
     }
 
-    impl<SPI, PINS> WriteIter<u8> for Spi<SPI, PINS, TransferModeBidi>
+    impl<SPI, PINS, WORD> WriteIter<WORD> for Spi<SPI, PINS, TransferModeBidi, WORD>
     where
-        Self: FullDuplex<u8, Error = Error>,
+        Self: FullDuplex<WORD, Error = Error>,
         SPI: Instance,
+        WORD: FrameSize,
     {
         type Error = Error;
 
         fn write_iter<WI>(&mut self, words: WI) -> Result<(), Self::Error>
         where
-            WI: IntoIterator<Item = u8>,
+            WI: IntoIterator<Item = WORD>,
         {
             for word in words.into_iter() {
                 nb::block!(self.send(word))?;
@@ -627,7 +1702,8 @@ mod blocking {
         }
     }
 
-    impl<SPI, PINS, TRANSFER_MODE, W: 'static> Transactional<W> for Spi<SPI, PINS, TRANSFER_MODE>
+    impl<SPI, PINS, TRANSFER_MODE, WORD, W: 'static> Transactional<W>
+        for Spi<SPI, PINS, TRANSFER_MODE, WORD>
     where
         Self: Write<W, Error = Error> + Transfer<W, Error = Error>,
     {
@@ -0,0 +1,80 @@
+//! embedded-hal 1.0 `SpiBus` implementation
+//!
+//! The transfers are blocking like the embedded-hal 0.2 ones; [`flush`](SpiBus::flush) waits
+//! for the last frame to leave the shift register, which is what a `SpiDevice` needs before it
+//! releases the chip select.
+
+use super::{Error, FrameSize, Instance, Spi, TransferModeNormal};
+use embedded_hal_one::spi::{self, ErrorKind, ErrorType, SpiBus};
+
+impl spi::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Overrun => ErrorKind::Overrun,
+            Error::ModeFault => ErrorKind::ModeFault,
+            Error::Crc | Error::Timeout | Error::InvalidFrequency => ErrorKind::Other,
+        }
+    }
+}
+
+impl<SPI, PINS, WORD> ErrorType for Spi<SPI, PINS, TransferModeNormal, WORD>
+where
+    SPI: Instance,
+{
+    type Error = Error;
+}
+
+impl<SPI, PINS, WORD> SpiBus<WORD> for Spi<SPI, PINS, TransferModeNormal, WORD>
+where
+    SPI: Instance,
+    WORD: FrameSize + Default + 'static,
+{
+    /// Reads `words`, clocking out zeros
+    fn read(&mut self, words: &mut [WORD]) -> Result<(), Self::Error> {
+        for word in words.iter_mut() {
+            nb::block!(self.check_send(WORD::default()))?;
+            *word = nb::block!(self.check_read())?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, words: &[WORD]) -> Result<(), Self::Error> {
+        for word in words {
+            nb::block!(self.check_send(*word))?;
+            nb::block!(self.check_read())?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `write` while reading into `read`
+    ///
+    /// The transfer lasts as long as the longer buffer: zeros are clocked out once `write` is
+    /// exhausted, and the frames received beyond the length of `read` are discarded.
+    fn transfer(&mut self, read: &mut [WORD], write: &[WORD]) -> Result<(), Self::Error> {
+        let len = read.len().max(write.len());
+        for i in 0..len {
+            let word = write.get(i).copied().unwrap_or_default();
+            nb::block!(self.check_send(word))?;
+            let received = nb::block!(self.check_read())?;
+            if let Some(slot) = read.get_mut(i) {
+                *slot = received;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [WORD]) -> Result<(), Self::Error> {
+        Spi::transfer_in_place(self, words)
+    }
+
+    /// Waits until the last frame has been shifted out and the bus is idle (`BSY` clear)
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        while self.spi.sr.read().txe().bit_is_clear() {}
+        while self.spi.sr.read().bsy().bit_is_set() {}
+
+        Ok(())
+    }
+}
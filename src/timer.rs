@@ -12,12 +12,22 @@ use void::Void;
 use crate::pac::RCC;
 
 use crate::rcc::{self, Clocks};
-use crate::time::Hertz;
+use crate::time::{Hertz, MilliSeconds};
 
 #[cfg(feature = "rtic")]
 #[cfg(not(feature = "stm32f410"))]
 pub mod monotonic;
 
+pub mod capture;
+pub mod counter;
+pub mod etr_counter;
+pub mod ppm;
+pub mod scheduler;
+pub mod sync;
+
+#[cfg(feature = "time-driver-tim5")]
+pub mod time_driver;
+
 /// Timer wrapper
 pub struct Timer<TIM> {
     pub(crate) tim: TIM,
@@ -28,13 +38,28 @@ pub struct Timer<TIM> {
 pub struct CountDownTimer<TIM> {
     tim: TIM,
     clk: Hertz,
+    state: State,
+}
+
+/// Progress of a `CountDownTimer`, telling the `cancel` errors apart
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+enum State {
+    /// Never started, or cancelled
+    Idle,
+    Running,
+    /// Stopped by itself after a one-pulse timeout
+    Expired,
 }
 
 impl<TIM> Timer<TIM> {
     /// Creates CountDownTimer
     pub fn count_down(self) -> CountDownTimer<TIM> {
         let Self { tim, clk } = self;
-        CountDownTimer { tim, clk }
+        CountDownTimer {
+            tim,
+            clk,
+            state: State::Idle,
+        }
     }
 }
 
@@ -61,11 +86,24 @@ pub enum Event {
     TimeOut,
 }
 
+/// Events that generate an update interrupt (`URS`)
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum UpdateRequestSource {
+    /// Counter overflow/underflow, setting UG and slave mode controller resets
+    Any,
+    /// Counter overflow/underflow only
+    OverflowOnly,
+}
+
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum Error {
-    /// CountDownTimer is disabled
+    /// CountDownTimer is disabled: it was never started, or already cancelled
     Disabled,
+    /// CountDownTimer stopped by itself after a one-pulse timeout
+    Expired,
     WrongAutoReload,
+    /// The timeout can't be represented by the reload value of the timer
+    ImpossibleTimeout,
 }
 
 impl Timer<SYST> {
@@ -99,6 +137,63 @@ impl CountDownTimer<SYST> {
     }
 }
 
+impl CountDownTimer<SYST> {
+    /// Starts the timer at frequency `timeout`, or returns `Error::ImpossibleTimeout`
+    ///
+    /// The 24-bit reload value limits the period to 2^24 core clock cycles, about 0.1 s at
+    /// 168 MHz, and the frequency can't be zero or exceed the core clock. `CountDown::start`
+    /// panics in these cases instead.
+    pub fn try_start<T>(&mut self, timeout: T) -> Result<(), Error>
+    where
+        T: Into<Hertz>,
+    {
+        let ticks = self
+            .clk
+            .0
+            .checked_div(timeout.into().0)
+            .ok_or(Error::ImpossibleTimeout)?;
+        if ticks == 0 || ticks > (1 << 24) {
+            return Err(Error::ImpossibleTimeout);
+        }
+
+        self.tim.set_reload(ticks - 1);
+        self.tim.clear_current();
+        self.tim.enable_counter();
+        self.state = State::Running;
+        Ok(())
+    }
+
+    /// Returns the number of core clock cycles until the next timeout
+    ///
+    /// Right after `start` this is the whole period, and it decreases to one cycle right
+    /// before the timeout.
+    pub fn remaining(&self) -> u32 {
+        match SYST::get_current() {
+            // the reload value is loaded at the next cycle
+            0 => SYST::get_reload() + 1,
+            current => current,
+        }
+    }
+
+    /// Rescales the reload value to new `clocks`, keeping the period
+    ///
+    /// Call this after the system clock was changed at runtime. The current period restarts.
+    /// Fails with `Error::ImpossibleTimeout`, leaving the timer unchanged, if the period doesn't
+    /// fit the 24-bit reload value at the new clock.
+    pub fn reconfigure(&mut self, clocks: &Clocks) -> Result<(), Error> {
+        let clk = clocks.sysclk();
+        let ticks = u64::from(SYST::get_reload() + 1) * u64::from(clk.0) / u64::from(self.clk.0);
+        if ticks == 0 || ticks > 1 << 24 {
+            return Err(Error::ImpossibleTimeout);
+        }
+        self.clk = clk;
+
+        self.tim.set_reload(ticks as u32 - 1);
+        self.tim.clear_current();
+        Ok(())
+    }
+}
+
 impl CountDown for CountDownTimer<SYST> {
     type Time = Hertz;
 
@@ -106,13 +201,7 @@ impl CountDown for CountDownTimer<SYST> {
     where
         T: Into<Hertz>,
     {
-        let rvr = self.clk.0 / timeout.into().0 - 1;
-
-        assert!(rvr < (1 << 24));
-
-        self.tim.set_reload(rvr);
-        self.tim.clear_current();
-        self.tim.enable_counter();
+        self.try_start(timeout).unwrap();
     }
 
     fn wait(&mut self) -> nb::Result<(), Void> {
@@ -128,11 +217,12 @@ impl Cancel for CountDownTimer<SYST> {
     type Error = Error;
 
     fn cancel(&mut self) -> Result<(), Self::Error> {
-        if !self.tim.is_counter_enabled() {
+        if self.state == State::Idle || !self.tim.is_counter_enabled() {
             return Err(Self::Error::Disabled);
         }
 
         self.tim.disable_counter();
+        self.state = State::Idle;
         Ok(())
     }
 }
@@ -196,8 +286,13 @@ mod sealed {
         fn is_counter_enabled(&self) -> bool;
         fn reset_counter(&mut self);
         fn set_prescaler(&mut self, psc: u16);
+        fn read_prescaler(&self) -> u16;
+        fn read_auto_reload(&self) -> u32;
+        fn read_count(&self) -> u32;
         fn set_auto_reload(&mut self, arr: u32) -> Result<(), super::Error>;
         fn trigger_update(&mut self);
+        fn set_update_request_source(&mut self, overflow_only: bool);
+        fn set_one_pulse_mode(&mut self, opm: bool);
         fn clear_update_interrupt_flag(&mut self);
         fn listen_update_interrupt(&mut self, b: bool);
         fn get_update_interrupt_flag(&self) -> bool;
@@ -208,6 +303,8 @@ pub(crate) use sealed::General;
 pub trait Instance:
     crate::Sealed + rcc::Enable + rcc::Reset + rcc::BusTimerClock + General
 {
+    /// Largest counter value, `u16::MAX` for 16-bit and `u32::MAX` for 32-bit timers
+    const MAX_AUTO_RELOAD: u32;
 }
 
 impl<TIM> Timer<TIM>
@@ -234,7 +331,9 @@ where
 macro_rules! hal {
     ($($TIM:ty: $bits:ty,)+) => {
         $(
-            impl Instance for $TIM { }
+            impl Instance for $TIM {
+                const MAX_AUTO_RELOAD: u32 = <$bits>::MAX as u32;
+            }
 
             impl General for $TIM {
                 type Width = $bits;
@@ -260,8 +359,20 @@ macro_rules! hal {
                     self.psc.write(|w| w.psc().bits(psc) );
                 }
                 #[inline(always)]
+                fn read_prescaler(&self) -> u16 {
+                    self.psc.read().psc().bits()
+                }
+                #[inline(always)]
+                fn read_auto_reload(&self) -> u32 {
+                    self.arr.read().bits()
+                }
+                #[inline(always)]
+                fn read_count(&self) -> u32 {
+                    self.cnt.read().bits()
+                }
+                #[inline(always)]
                 fn set_auto_reload(&mut self, arr: u32) -> Result<(), Error> {
-                    if arr > 0 && arr <= <$bits>::MAX as u32 {
+                    if arr > 0 && arr <= <Self as Instance>::MAX_AUTO_RELOAD {
                         Ok(self.arr.write(|w| unsafe { w.bits(arr) }))
                     } else {
                         Err(Error::WrongAutoReload)
@@ -269,9 +380,18 @@ macro_rules! hal {
                 }
                 #[inline(always)]
                 fn trigger_update(&mut self) {
+                    let urs = self.cr1.read().urs().bit();
                     self.cr1.modify(|_, w| w.urs().set_bit());
                     self.egr.write(|w| w.ug().set_bit());
-                    self.cr1.modify(|_, w| w.urs().clear_bit());
+                    self.cr1.modify(|_, w| w.urs().bit(urs));
+                }
+                #[inline(always)]
+                fn set_update_request_source(&mut self, overflow_only: bool) {
+                    self.cr1.modify(|_, w| w.urs().bit(overflow_only));
+                }
+                #[inline(always)]
+                fn set_one_pulse_mode(&mut self, opm: bool) {
+                    self.cr1.modify(|_, w| w.opm().bit(opm));
                 }
                 #[inline(always)]
                 fn clear_update_interrupt_flag(&mut self) {
@@ -330,6 +450,105 @@ where
         }
     }
 
+    /// Selects which events raise the update interrupt flag
+    ///
+    /// With [`UpdateRequestSource::OverflowOnly`], software updates and resets by a master
+    /// timer no longer cause an interrupt.
+    pub fn set_update_request_source(&mut self, source: UpdateRequestSource) {
+        self.tim
+            .set_update_request_source(source == UpdateRequestSource::OverflowOnly);
+    }
+
+    /// Writes the prescaler, which takes effect at the next update event
+    ///
+    /// The current period completes with the old prescaler, so frequency sweeps change
+    /// smoothly from one period to the next.
+    pub fn set_prescaler_deferred(&mut self, psc: u16) {
+        self.tim.set_prescaler(psc);
+    }
+
+    /// Writes the prescaler and applies it right away
+    ///
+    /// This generates an update event, which restarts the counter: the period in progress is
+    /// cut short, and the update interrupt flag is not set for it.
+    pub fn set_prescaler_immediate(&mut self, psc: u16) {
+        self.tim.set_prescaler(psc);
+        self.tim.trigger_update();
+    }
+
+    /// Starts the timer in one-pulse mode, expiring once after `timeout`
+    ///
+    /// Use [`poll_done`](Self::poll_done) to check for completion. The counter stops by itself
+    /// when it expires, so the timer has to be re-armed explicitly.
+    ///
+    /// Fails with `Error::ImpossibleTimeout` for a zero timeout, or one longer than
+    /// `65536 * 65535` timer clock cycles, about 51 s at 84 MHz.
+    pub fn oneshot(&mut self, timeout: MilliSeconds) -> Result<(), Error> {
+        let ticks = u64::from(self.clk.0) * u64::from(timeout.0) / 1_000;
+        // the 16-bit limit suits all timers, this doesn't know the width of `TIM`
+        let (psc, arr) = split_ticks(ticks, u32::from(u16::MAX)).ok_or(Error::ImpossibleTimeout)?;
+
+        // pause
+        self.tim.disable_counter();
+        // reset counter
+        self.tim.reset_counter();
+
+        self.tim.set_prescaler(psc);
+        self.tim.set_auto_reload(arr)?;
+
+        // Trigger update event to load the registers
+        self.tim.trigger_update();
+        self.tim.clear_update_interrupt_flag();
+
+        // counter stops at the next update event
+        self.tim.set_one_pulse_mode(true);
+        self.tim.enable_counter();
+        self.state = State::Running;
+        Ok(())
+    }
+
+    /// Returns `true` once when a timeout started with [`oneshot`](Self::oneshot) has expired
+    pub fn poll_done(&mut self) -> bool {
+        if self.tim.get_update_interrupt_flag() {
+            false
+        } else {
+            self.tim.clear_update_interrupt_flag();
+            self.state = State::Expired;
+            true
+        }
+    }
+
+    /// Returns the prescaler value (`PSC`): a counter tick lasts `PSC + 1` timer clock cycles
+    pub fn prescaler(&self) -> u16 {
+        self.tim.read_prescaler()
+    }
+
+    /// Returns the auto-reload value (`ARR`): a period lasts `ARR + 1` counter ticks
+    pub fn auto_reload(&self) -> u32 {
+        self.tim.read_auto_reload()
+    }
+
+    /// Returns the frequency the timer actually runs at
+    ///
+    /// The timer clock is divided by `(PSC + 1) * (ARR + 1)`, which can't match every
+    /// frequency: `start` truncates the ratio of the timer clock to the requested frequency and
+    /// splits it into prescaler and auto-reload values. The frequency ends up slightly lower
+    /// than requested, by a relative error of up to about `(PSC + 1) / ARR`. After a clock
+    /// change, [`reconfigure`](Self::reconfigure) recomputes both values.
+    pub fn frequency(&self) -> Hertz {
+        let ticks = (u64::from(self.prescaler()) + 1) * (u64::from(self.auto_reload()) + 1);
+        Hertz((u64::from(self.clk.0) / ticks) as u32)
+    }
+
+    /// Returns the number of counter ticks until the next timeout
+    ///
+    /// A counter tick lasts `PSC + 1` cycles of the timer clock. Right after `start` this is
+    /// the whole period, and it decreases to one tick right before the timeout.
+    pub fn remaining(&self) -> u32 {
+        let arr = self.tim.read_auto_reload();
+        arr.saturating_sub(self.tim.read_count()).saturating_add(1)
+    }
+
     /// Releases the TIM peripheral
     pub fn release(mut self) -> TIM {
         // pause counter
@@ -338,6 +557,104 @@ where
     }
 }
 
+impl<TIM> CountDownTimer<TIM>
+where
+    TIM: Instance,
+{
+    /// Recomputes prescaler and auto-reload for new `clocks`, keeping the period
+    ///
+    /// Call this after the system clock was changed at runtime. The current period restarts.
+    /// Fails with `Error::ImpossibleTimeout`, leaving the timer unchanged, if the period can't
+    /// be represented at the new clock.
+    pub fn reconfigure(&mut self, clocks: &Clocks) -> Result<(), Error> {
+        let clk = TIM::timer_clock(clocks);
+        // the update event comes after `(PSC + 1) * (ARR + 1)` timer clock cycles
+        let ticks = (u64::from(self.tim.read_prescaler()) + 1)
+            * (u64::from(self.tim.read_auto_reload()) + 1)
+            * u64::from(clk.0)
+            / u64::from(self.clk.0);
+        let (psc, arr) =
+            split_ticks(ticks, TIM::MAX_AUTO_RELOAD).ok_or(Error::ImpossibleTimeout)?;
+        self.clk = clk;
+
+        self.tim.set_prescaler(psc);
+        self.tim.set_auto_reload(arr)?;
+
+        // Trigger update event to load the registers
+        self.tim.trigger_update();
+        Ok(())
+    }
+
+    /// Blocks for at least `ns` nanoseconds
+    ///
+    /// The resolution is one cycle of the timer clock, on top of the time it takes to program
+    /// the timer. Like the other delays, this reprograms the timer and leaves it stopped.
+    pub fn delay_ns(&mut self, ns: u32) {
+        self.delay_ticks(u64::from(ns), 1_000_000_000);
+    }
+
+    /// Blocks for at least `us` microseconds
+    pub fn delay_us(&mut self, us: u32) {
+        self.delay_ticks(u64::from(us), 1_000_000);
+    }
+
+    /// Blocks for at least `ms` milliseconds
+    ///
+    /// Delays longer than one period of the timer at the largest prescaler, about 51 s for
+    /// a 16-bit timer at 84 MHz, are split into several one-pulse timeouts.
+    pub fn delay_ms(&mut self, ms: u32) {
+        self.delay_ticks(u64::from(ms), 1_000);
+    }
+
+    /// Blocks for `time / unit` seconds, rounded up to whole timer clock cycles
+    fn delay_ticks(&mut self, time: u64, unit: u64) {
+        // longest timeout of one pulse, in timer clock cycles
+        let max_ticks = (1 << 16) * u64::from(TIM::MAX_AUTO_RELOAD);
+
+        let mut ticks = (time * u64::from(self.clk.0) + unit - 1) / unit;
+        while ticks > 0 {
+            let chunk = ticks.min(max_ticks);
+            ticks -= chunk;
+
+            self.tim.disable_counter();
+            self.tim.reset_counter();
+
+            let (psc, arr) = split_ticks(chunk, TIM::MAX_AUTO_RELOAD).unwrap();
+            self.tim.set_prescaler(psc);
+            self.tim.set_auto_reload(arr).unwrap();
+
+            // Trigger update event to load the registers
+            self.tim.trigger_update();
+            self.tim.clear_update_interrupt_flag();
+
+            // counter stops at the next update event
+            self.tim.set_one_pulse_mode(true);
+            self.tim.enable_counter();
+            self.state = State::Running;
+
+            nb::block!(self.wait()).unwrap();
+        }
+    }
+}
+
+/// Splits a timeout of `ticks` timer clock cycles into prescaler and auto-reload values
+///
+/// The update event comes after `(PSC + 1) * (ARR + 1)` cycles, which is rounded up so the
+/// timeout is never short. Returns `None` for zero ticks, or more than the largest prescaler
+/// allows with `max_auto_reload`.
+pub(crate) fn split_ticks(ticks: u64, max_auto_reload: u32) -> Option<(u16, u32)> {
+    if ticks == 0 {
+        return None;
+    }
+    let max_auto_reload = u64::from(max_auto_reload);
+    let psc = u16((ticks - 1) / max_auto_reload).ok()?;
+    let psc_ticks = u64::from(psc) + 1;
+    let arr = ((ticks + psc_ticks - 1) / psc_ticks)
+        .saturating_sub(1)
+        .max(1);
+    Some((psc, arr as u32))
+}
+
 impl<TIM> CountDown for CountDownTimer<TIM>
 where
     TIM: General,
@@ -363,9 +680,14 @@ where
 
         // Trigger update event to load the registers
         self.tim.trigger_update();
+        // drop a flag left from before, which would fire the interrupt right after arming
+        self.tim.clear_update_interrupt_flag();
 
+        // periodic mode, in case the timer was used with `oneshot` before
+        self.tim.set_one_pulse_mode(false);
         // start counter
         self.tim.enable_counter();
+        self.state = State::Running;
     }
 
     fn wait(&mut self) -> nb::Result<(), Void> {
@@ -373,6 +695,10 @@ where
             Err(nb::Error::WouldBlock)
         } else {
             self.tim.clear_update_interrupt_flag();
+            // in periodic mode the counter keeps running
+            if !self.tim.is_counter_enabled() {
+                self.state = State::Expired;
+            }
             Ok(())
         }
     }
@@ -384,17 +710,138 @@ where
 {
     type Error = Error;
 
+    /// Stops the timer
+    ///
+    /// Returns `Error::Disabled` if the timer was never started or is already cancelled, and
+    /// `Error::Expired` if a one-pulse timeout has stopped the counter by itself.
     fn cancel(&mut self) -> Result<(), Self::Error> {
-        if !self.tim.is_counter_enabled() {
-            return Err(Self::Error::Disabled);
+        match self.state {
+            State::Idle => return Err(Self::Error::Disabled),
+            State::Expired => return Err(Self::Error::Expired),
+            // a one-pulse timeout that was not polled yet
+            State::Running if !self.tim.is_counter_enabled() => {
+                self.state = State::Expired;
+                return Err(Self::Error::Expired);
+            }
+            State::Running => {}
         }
 
         // disable counter
         self.tim.disable_counter();
+        self.state = State::Idle;
         Ok(())
     }
 }
 
+/// Timer expiring once per `start`, in one-pulse mode
+///
+/// Unlike [`CountDownTimer`], this is not `Periodic`: the counter stops by itself when it
+/// expires. `wait` returns `Ok` once after that and blocks again afterwards, until the timer
+/// is started again. `start` panics for the timeouts [`oneshot`](CountDownTimer::oneshot)
+/// rejects.
+pub struct OneShotTimer<TIM> {
+    timer: CountDownTimer<TIM>,
+}
+
+impl<TIM> Timer<TIM>
+where
+    TIM: General,
+{
+    /// Creates a timer expiring once per `start`
+    pub fn count_down_oneshot(self) -> OneShotTimer<TIM> {
+        OneShotTimer {
+            timer: self.count_down(),
+        }
+    }
+}
+
+impl<TIM> OneShotTimer<TIM>
+where
+    TIM: General,
+{
+    /// Starts listening for an `event`
+    pub fn listen(&mut self, event: Event) {
+        self.timer.listen(event)
+    }
+
+    /// Clears interrupt associated with `event`.
+    pub fn clear_interrupt(&mut self, event: Event) {
+        self.timer.clear_interrupt(event)
+    }
+
+    /// Stops listening for an `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self.timer.unlisten(event)
+    }
+
+    /// Releases the TIM peripheral
+    pub fn release(self) -> TIM {
+        self.timer.release()
+    }
+}
+
+impl<TIM> CountDown for OneShotTimer<TIM>
+where
+    TIM: General,
+{
+    type Time = MilliSeconds;
+
+    fn start<T>(&mut self, timeout: T)
+    where
+        T: Into<Self::Time>,
+    {
+        self.timer.oneshot(timeout.into()).unwrap();
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        if self.timer.poll_done() {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl<TIM> Cancel for OneShotTimer<TIM>
+where
+    TIM: General,
+{
+    type Error = Error;
+
+    fn cancel(&mut self) -> Result<(), Self::Error> {
+        self.timer.cancel()
+    }
+}
+
+macro_rules! advanced {
+    ($($TIM:ty,)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Sets the repetition counter (`RCR`): the update event, and with it the update
+                /// interrupt and DMA request, only occurs every `n + 1` counter cycles.
+                ///
+                /// With center-aligned counting, `1` gives one update per PWM period instead of
+                /// one per half period. The PWM frequency itself is not affected. The value is
+                /// preloaded and takes effect at the next update event, e.g. when `pwm` starts
+                /// the timer.
+                ///
+                /// As it lengthens the update period, the timer should not be used as a
+                /// `CountDownTimer` afterwards without resetting the repetition to `0`.
+                // some chip variants declare `.bits()` as unsafe, some don't
+                #[allow(unused_unsafe)]
+                pub fn set_repetition(&mut self, n: u8) {
+                    self.tim.rcr.write(|w| unsafe { w.rep().bits(n) });
+                }
+            }
+        )+
+    }
+}
+
+advanced!(crate::pac::TIM1,);
+
+#[cfg(feature = "tim8")]
+advanced!(crate::pac::TIM8,);
+
 // All F4xx parts have these timers.
 hal!(
     crate::pac::TIM1: u16,
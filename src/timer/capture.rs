@@ -0,0 +1,183 @@
+//! Input capture through DMA, for timestamping edges faster than interrupts can keep up
+//!
+//! Every captured edge raises the DMA request of its channel, so the stream copies the capture
+//! register into a buffer without any CPU involvement. The counter runs freely over 16 bits,
+//! which makes the wrapping difference of two successive timestamps the time between the edges.
+//!
+//! Decoding an IR remote, with TIM4 ticking once per microsecond:
+//!
+//! ```ignore
+//! let timer = Timer::new(dp.TIM4, &clocks);
+//! let ccr1 = timer.capture_dma::<C1, _>(&ir_pin, CaptureEdge::Both, 1.mhz());
+//! let streams = StreamsTuple::new(dp.DMA1);
+//! let mut transfer = Transfer::init_peripheral_to_memory(
+//!     streams.0,
+//!     ccr1,
+//!     buffer,
+//!     None,
+//!     DmaConfig::default().memory_increment(true),
+//! );
+//! transfer.start(|_| {});
+//! // later, the widths of the pulses are `buffer[i + 1].wrapping_sub(buffer[i])`
+//! ```
+use super::{CPin, General, Timer, C1, C2, C3, C4};
+use crate::dma::traits::{CCR1, CCR2, CCR3, CCR4};
+#[cfg(feature = "tim8")]
+use crate::pac::TIM8;
+use crate::pac::{TIM1, TIM5};
+#[cfg(feature = "tim2")]
+use crate::pac::{TIM2, TIM3, TIM4};
+use crate::time::Hertz;
+use cast::u16;
+
+/// Edges of the input that are captured
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureEdge {
+    Rising,
+    Falling,
+    Both,
+}
+
+pub trait Instance: super::Instance {
+    /// Maps capture `channel` (1 to 4) on its own input and enables the capture of `edge`
+    fn configure_capture(&mut self, channel: u8, edge: CaptureEdge);
+    /// Requests a DMA transfer on every capture of `channel`
+    fn enable_capture_dma(&mut self, channel: u8);
+    /// Enables the capture interrupt of `channel`
+    fn listen_capture(&mut self, channel: u8);
+    /// Returns the last value captured on `channel`, which clears its capture flag
+    fn read_capture(&self, channel: u8) -> u16;
+}
+
+/// A timer channel with a capture register that can be read by DMA
+pub trait CaptureChannel<TIM> {
+    /// Number of the channel, starting at 1
+    const CHANNEL: u8;
+    /// Wrapper selecting the capture register of the channel as DMA peripheral address
+    type Register;
+
+    fn register(tim: TIM) -> Self::Register;
+}
+
+macro_rules! channel {
+    ($($C:ident: $CCR:ident => $n:literal,)+) => {
+        $(
+            impl<TIM> CaptureChannel<TIM> for $C {
+                const CHANNEL: u8 = $n;
+                type Register = $CCR<TIM>;
+
+                fn register(tim: TIM) -> Self::Register {
+                    $CCR(tim)
+                }
+            }
+        )+
+    }
+}
+
+channel! {
+    C1: CCR1 => 1,
+    C2: CCR2 => 2,
+    C3: CCR3 => 3,
+    C4: CCR4 => 4,
+}
+
+impl<TIM: Instance> Timer<TIM> {
+    /// Captures the `edge`s on `pin` with a counter ticking at `tick`, and requests a DMA transfer
+    /// of every captured value
+    ///
+    /// The returned register is the peripheral of a peripheral to memory `Transfer` on the stream
+    /// and channel that the reference manual lists for the capture channel.
+    pub fn capture_dma<C, PIN>(
+        mut self,
+        _pin: &PIN,
+        edge: CaptureEdge,
+        tick: impl Into<Hertz>,
+    ) -> C::Register
+    where
+        C: CaptureChannel<TIM>,
+        PIN: CPin<C, TIM>,
+    {
+        let psc = u16(self.clk.0 / tick.into().0 - 1).unwrap();
+        self.tim.set_prescaler(psc);
+        // the DMA mapping transfers half-words, so let 32-bit timers wrap around as well
+        self.tim.set_auto_reload(0xffff).unwrap();
+        self.tim.trigger_update();
+
+        self.tim.configure_capture(C::CHANNEL, edge);
+        self.tim.enable_capture_dma(C::CHANNEL);
+        self.tim.enable_counter();
+
+        C::register(self.tim)
+    }
+}
+
+macro_rules! hal {
+    ($($TIM:ty,)+) => {
+        $(
+            impl Instance for $TIM {
+                fn configure_capture(&mut self, channel: u8, edge: CaptureEdge) {
+                    let c = u32::from(channel - 1);
+
+                    // CCxS = 01: capture the own input, without filter and prescaler
+                    let shift = (c % 2) * 8;
+                    let ccmr = |bits: u32| bits & !(0xff << shift) | 0b01 << shift;
+                    if c < 2 {
+                        self.ccmr1_input()
+                            .modify(|r, w| unsafe { w.bits(ccmr(r.bits())) });
+                    } else {
+                        self.ccmr2_input()
+                            .modify(|r, w| unsafe { w.bits(ccmr(r.bits())) });
+                    }
+
+                    // CCxNP, CCxP and CCxE
+                    let polarity = match edge {
+                        CaptureEdge::Rising => 0b0000,
+                        CaptureEdge::Falling => 0b0010,
+                        CaptureEdge::Both => 0b1010,
+                    };
+                    self.ccer.modify(|r, w| unsafe {
+                        w.bits(r.bits() & !(0b1011 << (4 * c)) | (polarity | 1) << (4 * c))
+                    });
+                }
+
+                fn enable_capture_dma(&mut self, channel: u8) {
+                    // CCxDE
+                    self.dier
+                        .modify(|r, w| unsafe { w.bits(r.bits() | 1 << (8 + channel)) });
+                }
+
+                fn listen_capture(&mut self, channel: u8) {
+                    // CCxIE
+                    self.dier
+                        .modify(|r, w| unsafe { w.bits(r.bits() | 1 << channel) });
+                }
+
+                fn read_capture(&self, channel: u8) -> u16 {
+                    (match channel {
+                        1 => self.ccr1.read().bits(),
+                        2 => self.ccr2.read().bits(),
+                        3 => self.ccr3.read().bits(),
+                        _ => self.ccr4.read().bits(),
+                    }) as u16
+                }
+            }
+        )+
+    }
+}
+
+hal! {
+    TIM1,
+    TIM5,
+}
+
+#[cfg(feature = "tim2")]
+hal! {
+    TIM2,
+    TIM3,
+    TIM4,
+}
+
+#[cfg(feature = "tim8")]
+hal! {
+    TIM8,
+}
@@ -0,0 +1,114 @@
+//! Free-running 64-bit tick counter on a general purpose timer
+//!
+//! The timer counts up over its whole range and the counter value is extended by the number of
+//! overflows, which the update interrupt handler accumulates. At `FREQ = 1_000_000` the result
+//! is a microsecond timestamp, see [`MicrosCounter`].
+//!
+//! ```ignore
+//! static COUNTER: Mutex<RefCell<Option<MicrosCounter<TIM2>>>> = Mutex::new(RefCell::new(None));
+//!
+//! let counter = Timer::new(dp.TIM2, &clocks).mono_counter();
+//! cortex_m::interrupt::free(|cs| COUNTER.borrow(cs).replace(Some(counter)));
+//! unsafe { NVIC::unmask(Interrupt::TIM2) };
+//!
+//! #[interrupt]
+//! fn TIM2() {
+//!     cortex_m::interrupt::free(|cs| {
+//!         COUNTER.borrow(cs).borrow_mut().as_mut().unwrap().on_update()
+//!     });
+//! }
+//! ```
+//!
+//! The overflow count is 32 bits wide. With a 32-bit timer (TIM2, TIM5), the timestamp covers
+//! the full 64 bits and wraps after 2^64 ticks. With a 16-bit timer, it wraps to zero after
+//! 2^48 ticks, about 8.9 years at 1 MHz, and the update interrupt fires every 65.5 ms at that
+//! rate, compared to every 71.6 minutes for a 32-bit timer.
+use super::{Instance, Timer};
+use crate::time::Hertz;
+use cast::u16;
+
+/// Free-running counter ticking at `FREQ` Hz, extended to 64 bits by counting overflows
+pub struct MonoCounter<TIM, const FREQ: u32> {
+    tim: TIM,
+    overflows: u32,
+}
+
+/// Counter ticking once per microsecond
+pub type MicrosCounter<TIM> = MonoCounter<TIM, 1_000_000>;
+
+impl<TIM> Timer<TIM>
+where
+    TIM: Instance,
+{
+    /// Starts the timer as free-running counter at `FREQ` Hz and enables the update interrupt
+    ///
+    /// # Panics
+    ///
+    /// If the timer clock is not a multiple of `FREQ` or the required prescaler doesn't fit in
+    /// 16 bits.
+    pub fn mono_counter<const FREQ: u32>(self) -> MonoCounter<TIM, FREQ> {
+        let Self { mut tim, clk } = self;
+
+        assert!(
+            clk.0 % FREQ == 0,
+            "timer clock is not a multiple of the counter frequency"
+        );
+        let psc = u16(clk.0 / FREQ - 1).expect("Prescaler does not fit in u16");
+
+        tim.disable_counter();
+        tim.reset_counter();
+        tim.set_prescaler(psc);
+        tim.set_auto_reload(TIM::MAX_AUTO_RELOAD).unwrap();
+        tim.set_one_pulse_mode(false);
+
+        // Trigger update event to load the registers
+        tim.trigger_update();
+        tim.clear_update_interrupt_flag();
+
+        tim.listen_update_interrupt(true);
+        tim.enable_counter();
+
+        MonoCounter { tim, overflows: 0 }
+    }
+}
+
+impl<TIM, const FREQ: u32> MonoCounter<TIM, FREQ>
+where
+    TIM: Instance,
+{
+    /// Returns the number of ticks since the counter was started
+    ///
+    /// An overflow that happened since the last [`on_update`](Self::on_update) call, because
+    /// the interrupt is masked or pending, is taken into account. Further overflows are lost.
+    pub fn now(&self) -> u64 {
+        let mut overflows = self.overflows;
+        let mut count = self.tim.read_count();
+        // NOTE the flag is set when this returns `false`
+        if !self.tim.get_update_interrupt_flag() {
+            overflows = overflows.wrapping_add(1);
+            // the first read may predate the overflow
+            count = self.tim.read_count();
+        }
+        u64::from(overflows) * (u64::from(TIM::MAX_AUTO_RELOAD) + 1) + u64::from(count)
+    }
+
+    /// Accounts for a counter overflow, call this from the interrupt handler of the timer
+    pub fn on_update(&mut self) {
+        if !self.tim.get_update_interrupt_flag() {
+            self.tim.clear_update_interrupt_flag();
+            self.overflows = self.overflows.wrapping_add(1);
+        }
+    }
+
+    /// Returns the frequency of the ticks
+    pub fn frequency(&self) -> Hertz {
+        Hertz(FREQ)
+    }
+
+    /// Stops the counter and releases the TIM peripheral
+    pub fn release(mut self) -> TIM {
+        self.tim.listen_update_interrupt(false);
+        self.tim.disable_counter();
+        self.tim
+    }
+}
@@ -0,0 +1,205 @@
+//! Counting pulses on the external trigger input (ETR)
+//!
+//! In external clock mode 2, the counter advances on every active edge of the ETR pin instead
+//! of the timer clock, which makes the timer a pulse counter for flow meters or single-channel
+//! encoders. TIM1, TIM2, TIM3, TIM4 and TIM8 have an ETR pin, see [`EtrPin`].
+//!
+//! ```ignore
+//! let etr = gpioa.pa0.into_alternate::<1>();
+//! let counter = Timer::new(dp.TIM2, &clocks).etr_counter(
+//!     etr,
+//!     EtrPolarity::ActiveHigh,
+//!     EtrPrescaler::Div1,
+//!     0b0011,
+//! );
+//! // later
+//! let pulses = counter.count();
+//! ```
+//!
+//! The ETR signal is sampled by the timer clock: after the ETR prescaler, it must stay below a
+//! quarter of the timer clock.
+//!
+//! Unlike [`PulseCounter`](crate::freq_counter::PulseCounter), which counts edges of channel 1,
+//! this leaves all four channels free for compare or PWM use.
+use super::Timer;
+#[cfg(feature = "gpiod")]
+use crate::gpio::gpiod::PD2;
+#[cfg(feature = "gpioe")]
+use crate::gpio::gpioe::{PE0, PE7};
+#[cfg(feature = "gpioi")]
+use crate::gpio::gpioi::PI3;
+use crate::gpio::{gpioa::*, Alternate};
+use crate::pac::TIM1;
+#[cfg(feature = "tim8")]
+use crate::pac::TIM8;
+#[cfg(feature = "tim2")]
+use crate::pac::{TIM2, TIM3, TIM4};
+pub use crate::pwm::EtrPolarity;
+
+/// Division of the ETR signal before it clocks the counter (`ETPS`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EtrPrescaler {
+    Div1 = 0b00,
+    Div2 = 0b01,
+    Div4 = 0b10,
+    Div8 = 0b11,
+}
+
+/// Pins that can be the external trigger input of `TIM`
+pub trait EtrPin<TIM> {}
+
+pub trait Instance: super::Instance {
+    /// Clocks the counter from ETR (`ECE`), `filter` is the raw `ETF` value
+    fn enable_external_clock(&mut self, polarity: EtrPolarity, prescaler: EtrPrescaler, filter: u8);
+    /// Switches the counter back to the internal clock
+    fn disable_external_clock(&mut self);
+}
+
+/// Timer counting the edges of its ETR input
+pub struct EtrCounter<TIM, PIN> {
+    tim: TIM,
+    pin: PIN,
+}
+
+impl<TIM: Instance> Timer<TIM> {
+    /// Starts counting edges of the ETR input `pin`, from zero up to the largest counter value
+    ///
+    /// The internal clock prescaler is not used, the counter advances by one per `prescaler`
+    /// edges: rising edges with `EtrPolarity::ActiveHigh`, falling edges with `ActiveLow`.
+    /// `filter` is the raw `ETF` value (0 to 15), which sets how many consistent samples make
+    /// an edge.
+    pub fn etr_counter<PIN: EtrPin<TIM>>(
+        self,
+        pin: PIN,
+        polarity: EtrPolarity,
+        prescaler: EtrPrescaler,
+        filter: u8,
+    ) -> EtrCounter<TIM, PIN> {
+        let Self { mut tim, .. } = self;
+
+        tim.disable_counter();
+        tim.set_prescaler(0);
+        tim.set_auto_reload(TIM::MAX_AUTO_RELOAD).unwrap();
+        tim.set_one_pulse_mode(false);
+
+        // Trigger update event to load the registers
+        tim.trigger_update();
+        tim.clear_update_interrupt_flag();
+
+        tim.enable_external_clock(polarity, prescaler, filter);
+        tim.reset_counter();
+        tim.enable_counter();
+
+        EtrCounter { tim, pin }
+    }
+}
+
+impl<TIM: Instance, PIN> EtrCounter<TIM, PIN> {
+    /// Returns the number of pulses counted, wrapping at the width of the counter
+    pub fn count(&self) -> u32 {
+        self.tim.read_count()
+    }
+
+    /// Restarts counting from zero
+    pub fn reset(&mut self) {
+        self.tim.reset_counter();
+    }
+
+    /// Stops counting and releases the TIM peripheral and the ETR pin
+    pub fn release(mut self) -> (TIM, PIN) {
+        self.tim.disable_counter();
+        self.tim.disable_external_clock();
+        (self.tim, self.pin)
+    }
+}
+
+macro_rules! hal {
+    ($($TIM:ty,)+) => {
+        $(
+            impl Instance for $TIM {
+                #[allow(unused_unsafe)]
+                fn enable_external_clock(
+                    &mut self,
+                    polarity: EtrPolarity,
+                    prescaler: EtrPrescaler,
+                    filter: u8,
+                ) {
+                    self.smcr.modify(|_, w| unsafe {
+                        w.etf()
+                            .bits(filter & 0xf)
+                            .etps()
+                            .bits(prescaler as u8)
+                            .etp()
+                            .bit(polarity == EtrPolarity::ActiveLow)
+                            .ece()
+                            .set_bit()
+                    });
+                }
+
+                fn disable_external_clock(&mut self) {
+                    self.smcr.modify(|_, w| w.ece().clear_bit());
+                }
+            }
+        )+
+    }
+}
+
+hal! {
+    TIM1,
+}
+
+#[cfg(feature = "tim2")]
+hal! {
+    TIM2,
+    TIM3,
+    TIM4,
+}
+
+#[cfg(feature = "tim8")]
+hal! {
+    TIM8,
+}
+
+macro_rules! etr_pin {
+    ($($TIM:ty: $PIN:ident, $AF:literal;)+) => {
+        $(
+            impl<Otype> EtrPin<$TIM> for $PIN<Alternate<Otype, $AF>> {}
+        )+
+    };
+}
+
+etr_pin! {
+    TIM1: PA12, 1;
+}
+
+#[cfg(feature = "gpioe")]
+etr_pin! {
+    TIM1: PE7, 1;
+}
+
+#[cfg(feature = "tim2")]
+etr_pin! {
+    TIM2: PA0, 1;
+    TIM2: PA5, 1;
+    TIM2: PA15, 1;
+}
+
+#[cfg(all(feature = "tim2", feature = "gpiod"))]
+etr_pin! {
+    TIM3: PD2, 2;
+}
+
+#[cfg(all(feature = "tim2", feature = "gpioe"))]
+etr_pin! {
+    TIM4: PE0, 2;
+}
+
+#[cfg(feature = "tim8")]
+etr_pin! {
+    TIM8: PA0, 3;
+}
+
+#[cfg(all(feature = "tim8", feature = "gpioi"))]
+etr_pin! {
+    TIM8: PI3, 3;
+}
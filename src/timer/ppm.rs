@@ -0,0 +1,108 @@
+//! Decoder of the PPM sum signal of RC receivers
+//!
+//! A PPM frame is a train of pulses whose rising edges are one channel value apart, typically
+//! 1000 µs to 2000 µs, followed by a longer gap that marks the start of the next frame. The
+//! rising edges are captured with a counter ticking every microsecond, and a gap of at least
+//! [`SYNC_GAP`] restarts the decoding at the first channel.
+//!
+//! ```ignore
+//! let mut ppm = Timer::new(dp.TIM3, &clocks).ppm_reader::<8, _>(ppm_pin);
+//!
+//! #[interrupt]
+//! fn TIM3() {
+//!     // the reader lives in a `Mutex<RefCell<Option<PpmReader<..>>>>`
+//!     ppm.on_capture();
+//! }
+//!
+//! let throttle = ppm.channels()[2];
+//! ```
+use super::capture::{CaptureEdge, Instance};
+use super::{CPin, General, Timer, C1};
+use cast::u16;
+
+/// Default shortest gap between two frames, in microseconds
+pub const SYNC_GAP: u16 = 3000;
+
+/// Decoder of `N` PPM channels on the first capture channel of `TIM`
+pub struct PpmReader<TIM, PIN, const N: usize> {
+    tim: TIM,
+    pin: PIN,
+    sync_gap: u16,
+    last: u16,
+    index: usize,
+    frame: [u16; N],
+    channels: [u16; N],
+}
+
+impl<TIM: Instance> Timer<TIM> {
+    /// Decodes the PPM signal on `pin`
+    ///
+    /// The capture interrupt of the channel is enabled, the interrupt handler of the timer has
+    /// to call [`PpmReader::on_capture`].
+    pub fn ppm_reader<const N: usize, PIN>(mut self, pin: PIN) -> PpmReader<TIM, PIN, N>
+    where
+        PIN: CPin<C1, TIM>,
+    {
+        // 1 MHz ticks, wrapping over 16 bits
+        let psc = u16(self.clk.0 / 1_000_000 - 1).unwrap();
+        self.tim.set_prescaler(psc);
+        self.tim.set_auto_reload(0xffff).unwrap();
+        self.tim.trigger_update();
+
+        self.tim.configure_capture(1, CaptureEdge::Rising);
+        self.tim.listen_capture(1);
+        self.tim.enable_counter();
+
+        PpmReader {
+            tim: self.tim,
+            pin,
+            sync_gap: SYNC_GAP,
+            last: 0,
+            index: N,
+            frame: [0; N],
+            channels: [0; N],
+        }
+    }
+}
+
+impl<TIM: Instance, PIN, const N: usize> PpmReader<TIM, PIN, N> {
+    /// Sets the shortest gap between frames, in microseconds
+    pub fn with_sync_gap(mut self, sync_gap: u16) -> Self {
+        self.sync_gap = sync_gap;
+        self
+    }
+
+    /// Handles a captured edge, to be called from the interrupt handler of the timer
+    pub fn on_capture(&mut self) {
+        let now = self.tim.read_capture(1);
+        let width = now.wrapping_sub(self.last);
+        self.last = now;
+
+        if width >= self.sync_gap {
+            // only frames with exactly `N` channels are published
+            if self.index == N {
+                self.channels = self.frame;
+            }
+            self.index = 0;
+        } else if self.index < N {
+            self.frame[self.index] = width;
+            self.index += 1;
+        } else {
+            // more channels than expected, drop the frame
+            self.index = N + 1;
+        }
+    }
+
+    /// Returns the channel values of the last complete frame, in microseconds
+    ///
+    /// All values are `0` until a frame was received.
+    pub fn channels(&self) -> [u16; N] {
+        self.channels
+    }
+
+    /// Stops decoding and returns the timer and the pin
+    pub fn release(mut self) -> (TIM, PIN) {
+        self.tim.disable_counter();
+        (self.tim, self.pin)
+    }
+}
@@ -0,0 +1,172 @@
+//! Periodic callbacks served by the update interrupt of a single timer
+//!
+//! ```ignore
+//! static SCHEDULER: Mutex<RefCell<Option<Scheduler<TIM2, 4>>>> = Mutex::new(RefCell::new(None));
+//!
+//! let timer = Timer::new(dp.TIM2, &clocks).count_down();
+//! let mut scheduler = Scheduler::new(timer, 1.khz());
+//! scheduler.add(10.ms(), poll_buttons).unwrap();
+//! scheduler.add(500.ms(), blink).unwrap();
+//!
+//! #[interrupt]
+//! fn TIM2() {
+//!     cortex_m::interrupt::free(|cs| {
+//!         if let Some(scheduler) = SCHEDULER.borrow(cs).borrow_mut().as_mut() {
+//!             scheduler.on_tick();
+//!         }
+//!     });
+//! }
+//! ```
+//!
+//! [`SysTickScheduler`] does the same at a fixed 1 kHz from SysTick, for when all hardware
+//! timers are in use.
+use super::{CountDownTimer, Event, General, Timer};
+use crate::time::{Hertz, MilliSeconds, U32Ext};
+use cortex_m::peripheral::SYST;
+use embedded_hal::timer::CountDown;
+
+/// All task slots of the [`Scheduler`] are in use
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct SchedulerFull;
+
+#[derive(Clone, Copy)]
+struct Task {
+    interval: u32,
+    remaining: u32,
+    callback: fn(),
+}
+
+struct Tasks<const N: usize>([Option<Task>; N]);
+
+impl<const N: usize> Tasks<N> {
+    const fn new() -> Self {
+        Self([None; N])
+    }
+
+    fn add(&mut self, ticks: u32, callback: fn()) -> Result<(), SchedulerFull> {
+        let slot = self
+            .0
+            .iter_mut()
+            .find(|task| task.is_none())
+            .ok_or(SchedulerFull)?;
+        *slot = Some(Task {
+            interval: ticks,
+            remaining: ticks,
+            callback,
+        });
+        Ok(())
+    }
+
+    fn remove(&mut self, callback: fn()) {
+        for slot in self.0.iter_mut() {
+            if matches!(slot, Some(task) if task.callback as usize == callback as usize) {
+                *slot = None;
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        for task in self.0.iter_mut().flatten() {
+            task.remaining -= 1;
+            if task.remaining == 0 {
+                task.remaining = task.interval;
+                (task.callback)();
+            }
+        }
+    }
+}
+
+/// Runs up to `N` callbacks, each at its own interval, from the base tick of one timer
+pub struct Scheduler<TIM, const N: usize> {
+    timer: CountDownTimer<TIM>,
+    tick: Hertz,
+    tasks: Tasks<N>,
+}
+
+impl<TIM, const N: usize> Scheduler<TIM, N>
+where
+    TIM: General,
+{
+    /// Starts `timer` at the base `tick` rate and enables its update interrupt
+    ///
+    /// The interrupt still has to be unmasked in the NVIC, and its handler has to call
+    /// [`on_tick`](Self::on_tick).
+    pub fn new(mut timer: CountDownTimer<TIM>, tick: Hertz) -> Self {
+        timer.start(tick);
+        timer.listen(Event::TimeOut);
+        Self {
+            timer,
+            tick,
+            tasks: Tasks::new(),
+        }
+    }
+
+    /// Registers `callback` to run every `interval`
+    ///
+    /// Intervals that are not a multiple of the base tick are rounded to the nearest tick, and
+    /// never below one tick.
+    pub fn add(&mut self, interval: MilliSeconds, callback: fn()) -> Result<(), SchedulerFull> {
+        let ticks = (u64::from(interval.0) * u64::from(self.tick.0) + 500) / 1_000;
+        let ticks = ticks.max(1).min(u64::from(u32::MAX)) as u32;
+        self.tasks.add(ticks, callback)
+    }
+
+    /// Unregisters all tasks running `callback`
+    pub fn remove(&mut self, callback: fn()) {
+        self.tasks.remove(callback);
+    }
+
+    /// Advances the schedule by one base tick, running every task that is due
+    ///
+    /// Call this from the timer interrupt handler; it also clears the interrupt flag.
+    pub fn on_tick(&mut self) {
+        self.timer.clear_interrupt(Event::TimeOut);
+        self.tasks.tick();
+    }
+
+    /// Disables the update interrupt and returns the timer
+    pub fn release(mut self) -> CountDownTimer<TIM> {
+        self.timer.unlisten(Event::TimeOut);
+        self.timer
+    }
+}
+
+/// Runs up to `N` callbacks with millisecond intervals from the SysTick exception
+pub struct SysTickScheduler<const N: usize> {
+    timer: CountDownTimer<SYST>,
+    tasks: Tasks<N>,
+}
+
+impl<const N: usize> SysTickScheduler<N> {
+    /// Starts SysTick at 1 kHz and enables its exception, whose handler has to call
+    /// [`tick`](Self::tick)
+    pub fn new(timer: Timer<SYST>) -> Self {
+        let mut timer = timer.start_count_down(1.khz());
+        timer.listen(Event::TimeOut);
+        Self {
+            timer,
+            tasks: Tasks::new(),
+        }
+    }
+
+    /// Registers `callback` to run every `interval`, which is at least one millisecond
+    pub fn every(&mut self, interval: MilliSeconds, callback: fn()) -> Result<(), SchedulerFull> {
+        self.tasks.add(interval.0.max(1), callback)
+    }
+
+    /// Unregisters all tasks running `callback`
+    pub fn remove(&mut self, callback: fn()) {
+        self.tasks.remove(callback);
+    }
+
+    /// Advances the schedule by one millisecond, running every task that is due
+    pub fn tick(&mut self) {
+        self.tasks.tick();
+    }
+
+    /// Disables the SysTick exception and returns the timer
+    pub fn release(mut self) -> CountDownTimer<SYST> {
+        self.timer.unlisten(Event::TimeOut);
+        self.timer
+    }
+}
@@ -0,0 +1,193 @@
+//! Master/slave synchronization of timers through their internal trigger (ITR) inputs
+//!
+//! A master timer outputs an event on its TRGO line, which the slave timer selects as trigger
+//! input. Only these connections exist in hardware:
+//!
+//! | Slave | ITR0 | ITR1 | ITR2 | ITR3 |
+//! |-------|------|------|------|------|
+//! | TIM1  | TIM5 | TIM2 | TIM3 | TIM4 |
+//! | TIM2  | TIM1 | TIM8 | TIM3 | TIM4 |
+//! | TIM3  | TIM1 | TIM2 | TIM5 | TIM4 |
+//! | TIM4  | TIM1 | TIM2 | TIM3 | TIM8 |
+//! | TIM5  | TIM2 | TIM3 | TIM4 | TIM8 |
+//! | TIM8  | TIM1 | TIM2 | TIM4 | TIM5 |
+//!
+//! They are encoded in the [`InternalTrigger`] trait, so an impossible pairing does not compile.
+//!
+//! A typical motor control setup restarts TIM8 on every TIM1 update and uses a compare event of
+//! TIM8 to start the ADC at a fixed offset into the PWM period:
+//!
+//! ```ignore
+//! let mut pwm_timer = Timer::new(dp.TIM1, &clocks);
+//! pwm_timer.set_master_mode(MasterMode::Update);
+//! let mut adc_timer = Timer::new(dp.TIM8, &clocks);
+//! adc_timer.slave_to::<TIM1>(SlaveMode::Reset);
+//! adc_timer.trgo_on_compare(sample_offset);
+//! // configure the ADC with `ExternalTrigger::Tim_8_trgo`
+//! ```
+use super::Timer;
+#[cfg(feature = "tim8")]
+use crate::pac::TIM8;
+use crate::pac::{TIM1, TIM5};
+#[cfg(feature = "tim2")]
+use crate::pac::{TIM2, TIM3, TIM4};
+
+/// Event output on the TRGO line of a master timer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MasterMode {
+    /// UG bit of the EGR register
+    Reset = 0b000,
+    /// Counter enable
+    Enable = 0b001,
+    /// Update event
+    Update = 0b010,
+    /// Capture/compare 1 flag being set
+    ComparePulse = 0b011,
+    /// OC1REF signal
+    Oc1Ref = 0b100,
+    /// OC2REF signal
+    Oc2Ref = 0b101,
+    /// OC3REF signal
+    Oc3Ref = 0b110,
+    /// OC4REF signal
+    Oc4Ref = 0b111,
+}
+
+/// Reaction of a slave timer to its trigger input
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlaveMode {
+    /// Restart the counter on the rising edge of the trigger
+    Reset = 0b100,
+    /// Count only while the trigger is high
+    Gated = 0b101,
+    /// Start the counter on the rising edge of the trigger
+    Trigger = 0b110,
+}
+
+pub trait Instance: crate::Sealed {
+    fn set_master_mode(&mut self, mode: MasterMode);
+    fn set_slave_mode(&mut self, mode: SlaveMode, itr: u8);
+    fn set_compare4(&mut self, ccr: u32);
+}
+
+/// Marks `MASTER` as connected to an internal trigger input of this timer
+pub trait InternalTrigger<MASTER>: Instance {
+    /// Index of the ITR input, used as TS value of the SMCR register
+    const ITR: u8;
+}
+
+impl<TIM: Instance> Timer<TIM> {
+    /// Selects the event output on TRGO
+    pub fn set_master_mode(&mut self, mode: MasterMode) {
+        self.tim.set_master_mode(mode);
+    }
+
+    /// Drives TRGO from a compare of the counter against `ccr` on channel 4
+    ///
+    /// The rising edge of TRGO occurs `ccr` ticks after the counter was restarted, which makes
+    /// this the trigger to use for sampling at a fixed point of the period.
+    pub fn trgo_on_compare(&mut self, ccr: u32) {
+        self.tim.set_compare4(ccr);
+        self.tim.set_master_mode(MasterMode::Oc4Ref);
+    }
+
+    /// Makes this timer react to the TRGO output of `MASTER`
+    pub fn slave_to<MASTER>(&mut self, mode: SlaveMode)
+    where
+        TIM: InternalTrigger<MASTER>,
+    {
+        self.tim
+            .set_slave_mode(mode, <TIM as InternalTrigger<MASTER>>::ITR);
+    }
+}
+
+macro_rules! hal {
+    ($($TIM:ty,)+) => {
+        $(
+            impl Instance for $TIM {
+                // some chip variants declare `.bits()` as unsafe, some don't
+                #[allow(unused_unsafe)]
+                fn set_master_mode(&mut self, mode: MasterMode) {
+                    self.cr2.modify(|_, w| unsafe { w.mms().bits(mode as u8) });
+                }
+
+                #[allow(unused_unsafe)]
+                fn set_slave_mode(&mut self, mode: SlaveMode, itr: u8) {
+                    self.smcr
+                        .modify(|_, w| unsafe { w.ts().bits(itr).sms().bits(mode as u8) });
+                }
+
+                #[allow(unused_unsafe)]
+                fn set_compare4(&mut self, ccr: u32) {
+                    // PWM mode 2: OC4REF is low while the counter is below CCR4 and rises
+                    // when it reaches CCR4
+                    self.ccmr2_output()
+                        .modify(|_, w| unsafe { w.oc4m().bits(0b111) });
+                    self.ccr4.write(|w| unsafe { w.bits(ccr) });
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! itr {
+    ($($SLAVE:ty: $MASTER:ty => $itr:literal,)+) => {
+        $(
+            impl InternalTrigger<$MASTER> for $SLAVE {
+                const ITR: u8 = $itr;
+            }
+        )+
+    }
+}
+
+hal! {
+    TIM1,
+    TIM5,
+}
+
+#[cfg(feature = "tim2")]
+hal! {
+    TIM2,
+    TIM3,
+    TIM4,
+}
+
+#[cfg(feature = "tim8")]
+hal! {
+    TIM8,
+}
+
+itr! {
+    TIM1: TIM5 => 0,
+}
+
+#[cfg(feature = "tim2")]
+itr! {
+    TIM1: TIM2 => 1,
+    TIM1: TIM3 => 2,
+    TIM1: TIM4 => 3,
+    TIM2: TIM1 => 0,
+    TIM2: TIM3 => 2,
+    TIM2: TIM4 => 3,
+    TIM3: TIM1 => 0,
+    TIM3: TIM2 => 1,
+    TIM3: TIM5 => 2,
+    TIM3: TIM4 => 3,
+    TIM4: TIM1 => 0,
+    TIM4: TIM2 => 1,
+    TIM4: TIM3 => 2,
+    TIM5: TIM2 => 0,
+    TIM5: TIM3 => 1,
+    TIM5: TIM4 => 2,
+}
+
+#[cfg(feature = "tim8")]
+itr! {
+    TIM2: TIM8 => 1,
+    TIM4: TIM8 => 3,
+    TIM5: TIM8 => 3,
+    TIM8: TIM1 => 0,
+    TIM8: TIM2 => 1,
+    TIM8: TIM4 => 2,
+    TIM8: TIM5 => 3,
+}
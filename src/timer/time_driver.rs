@@ -0,0 +1,188 @@
+//! `embassy-time` driver based on TIM5
+//!
+//! TIM5 is a 32-bit timer available on all F4 parts. It runs at
+//! `embassy_time_driver::TICK_HZ` and is extended to a 64-bit timestamp by
+//! counting half periods: the update event and a compare on channel 1 at the
+//! middle of the range each advance the period counter. Channel 2 is used for
+//! the alarm that wakes the timer queue.
+//!
+//! Call [`init`] once before using `embassy-time`. The driver owns the `TIM5`
+//! interrupt handler.
+
+use core::cell::{Cell, RefCell};
+use core::sync::atomic::{compiler_fence, AtomicU32, Ordering};
+use core::task::Waker;
+
+use cortex_m::interrupt::{CriticalSection, Mutex};
+use cortex_m::peripheral::NVIC;
+use embassy_time_driver::{Driver, TICK_HZ};
+use embassy_time_queue_utils::Queue;
+
+use crate::pac::{interrupt, Interrupt, TIM5};
+use crate::rcc::Clocks;
+use crate::timer::Timer;
+
+/// Alarms further than this from the current period base are armed once the
+/// period counter has caught up with them
+const ALARM_WINDOW: u64 = 0xC000_0000;
+
+fn calc_now(period: u32, counter: u32) -> u64 {
+    (u64::from(period) << 31) + u64::from(counter ^ ((period & 1) << 31))
+}
+
+struct TimeDriver {
+    /// Number of half periods of the 32-bit counter elapsed
+    period: AtomicU32,
+    /// Timestamp of the next alarm, `u64::MAX` if none
+    alarm: Mutex<Cell<u64>>,
+    queue: Mutex<RefCell<Queue>>,
+}
+
+embassy_time_driver::time_driver_impl!(static DRIVER: TimeDriver = TimeDriver {
+    period: AtomicU32::new(0),
+    alarm: Mutex::new(Cell::new(u64::MAX)),
+    queue: Mutex::new(RefCell::new(Queue::new())),
+});
+
+/// Configures TIM5 as the `embassy-time` time base and enables its interrupt
+///
+/// # Panics
+///
+/// If the timer clock is not a multiple of `TICK_HZ` or the required prescaler
+/// doesn't fit in 16 bits.
+pub fn init(tim: TIM5, clocks: &Clocks) {
+    let Timer { tim, clk } = Timer::new(tim, clocks);
+
+    assert!(
+        clk.0 as u64 % TICK_HZ == 0,
+        "timer clock is not a multiple of TICK_HZ"
+    );
+    let psc = cast::u16(clk.0 as u64 / TICK_HZ - 1).expect("Prescaler does not fit in u16");
+
+    tim.cr1.modify(|_, w| w.cen().clear_bit());
+    tim.psc.write(|w| w.psc().bits(psc));
+    tim.arr.write(|w| unsafe { w.bits(u32::MAX) });
+    tim.ccr1.write(|w| unsafe { w.bits(0x8000_0000) });
+
+    // Load the prescaler without raising the update interrupt
+    tim.cr1.modify(|_, w| w.urs().set_bit());
+    tim.egr.write(|w| w.ug().set_bit());
+    tim.cr1.modify(|_, w| w.urs().clear_bit());
+    tim.sr.write(|w| unsafe { w.bits(0) });
+
+    tim.dier.write(|w| w.uie().set_bit().cc1ie().set_bit());
+    tim.cr1.modify(|_, w| w.cen().set_bit());
+
+    // Never released, the driver accesses the registers through `TIM5::ptr()`
+    core::mem::forget(tim);
+
+    // NOTE(unsafe) the handler below only touches the driver state
+    unsafe { NVIC::unmask(Interrupt::TIM5) };
+}
+
+impl TimeDriver {
+    fn regs() -> &'static crate::pac::tim5::RegisterBlock {
+        // NOTE(unsafe) TIM5 is owned by the driver after `init`
+        unsafe { &*TIM5::ptr() }
+    }
+
+    fn on_interrupt(&self) {
+        let tim = Self::regs();
+        let sr = tim.sr.read();
+        let dier = tim.dier.read();
+
+        // Flags are cleared by writing 0, writing 1 has no effect
+        tim.sr.write(|w| unsafe { w.bits(!sr.bits()) });
+
+        if sr.uif().bit_is_set() || sr.cc1if().bit_is_set() {
+            self.next_period();
+        }
+
+        if sr.cc2if().bit_is_set() && dier.cc2ie().bit_is_set() {
+            cortex_m::interrupt::free(|cs| self.trigger_alarm(cs));
+        }
+    }
+
+    fn next_period(&self) {
+        let period = self.period.fetch_add(1, Ordering::Relaxed) + 1;
+        let t = u64::from(period) << 31;
+
+        cortex_m::interrupt::free(|cs| {
+            let alarm = self.alarm.borrow(cs).get();
+            if alarm < t + ALARM_WINDOW {
+                Self::regs().dier.modify(|_, w| w.cc2ie().set_bit());
+            }
+        })
+    }
+
+    fn trigger_alarm(&self, cs: &CriticalSection) {
+        Self::regs().dier.modify(|_, w| w.cc2ie().clear_bit());
+        self.alarm.borrow(cs).set(u64::MAX);
+
+        let mut queue = self.queue.borrow(cs).borrow_mut();
+        let mut next = queue.next_expiration(self.now());
+        while !self.set_alarm(cs, next) {
+            next = queue.next_expiration(self.now());
+        }
+    }
+
+    /// Arms the alarm for `timestamp`, returns `false` if it has already passed
+    fn set_alarm(&self, cs: &CriticalSection, timestamp: u64) -> bool {
+        let tim = Self::regs();
+        self.alarm.borrow(cs).set(timestamp);
+
+        if timestamp == u64::MAX {
+            tim.dier.modify(|_, w| w.cc2ie().clear_bit());
+            return true;
+        }
+
+        let t = self.now();
+        if timestamp <= t {
+            tim.dier.modify(|_, w| w.cc2ie().clear_bit());
+            self.alarm.borrow(cs).set(u64::MAX);
+            return false;
+        }
+
+        tim.ccr2.write(|w| unsafe { w.bits(timestamp as u32) });
+
+        // Alarms too far in the future are armed by `next_period`
+        let diff = timestamp - t;
+        tim.dier.modify(|_, w| w.cc2ie().bit(diff < ALARM_WINDOW));
+
+        // The counter may have passed the compare value while it was written
+        let t = self.now();
+        if timestamp <= t {
+            tim.dier.modify(|_, w| w.cc2ie().clear_bit());
+            self.alarm.borrow(cs).set(u64::MAX);
+            return false;
+        }
+
+        true
+    }
+}
+
+impl Driver for TimeDriver {
+    fn now(&self) -> u64 {
+        let period = self.period.load(Ordering::Relaxed);
+        compiler_fence(Ordering::Acquire);
+        let counter = Self::regs().cnt.read().bits();
+        calc_now(period, counter)
+    }
+
+    fn schedule_wake(&self, at: u64, waker: &Waker) {
+        cortex_m::interrupt::free(|cs| {
+            let mut queue = self.queue.borrow(cs).borrow_mut();
+            if queue.schedule_wake(at, waker) {
+                let mut next = queue.next_expiration(self.now());
+                while !self.set_alarm(cs, next) {
+                    next = queue.next_expiration(self.now());
+                }
+            }
+        })
+    }
+}
+
+#[interrupt]
+fn TIM5() {
+    DRIVER.on_interrupt()
+}